@@ -0,0 +1,79 @@
+use crate::dwarf_data::Type;
+use nix::unistd::Pid;
+
+/// A user breakpoint, as listed by "info breakpoints".
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    pub num: usize,
+    /// Where the location resolved to. Inlined or duplicated code gives several addresses.
+    pub addrs: Vec<usize>,
+    /// What the user asked for, e.g. "main", "12" or "*0x401136", so that the breakpoint can be
+    /// set again when the inferior execs a new program
+    pub location: String,
+    pub enabled: bool,
+    pub hit_count: usize,
+    /// Only stop if this evaluates to true in the stopped frame
+    pub condition: Option<String>,
+    /// Number of upcoming hits to skip
+    pub ignore_count: usize,
+    /// Delete the breakpoint once it stops the inferior ("tbreak")
+    pub temporary: bool,
+    /// Set while the location isn't known yet, e.g. a function in a shared library that hasn't
+    /// been loaded. `addrs` is empty then.
+    pub pending: bool,
+}
+
+impl Breakpoint {
+    pub fn new(num: usize, addrs: Vec<usize>, location: &str) -> Breakpoint {
+        Breakpoint {
+            num,
+            addrs,
+            location: location.to_string(),
+            enabled: true,
+            hit_count: 0,
+            condition: None,
+            ignore_count: 0,
+            temporary: false,
+            pending: false,
+        }
+    }
+}
+
+/// A breakpoint the debugger sets for itself, e.g. for "until", which isn't listed in the
+/// breakpoint table. It only lasts until the inferior stops.
+#[derive(Debug, Clone)]
+pub struct InternalBreakpoint {
+    pub addr: usize,
+    /// Only stop if rsp is at least this, i.e. not in a deeper (e.g. recursive) call
+    pub min_sp: usize,
+}
+
+/// A hardware watchpoint. Watchpoints share their numbers with breakpoints.
+#[derive(Debug, Clone)]
+pub struct Watchpoint {
+    pub num: usize,
+    /// What the user asked to watch, e.g. "count" or "*0x601040"
+    pub expr: String,
+    pub addr: usize,
+    pub entity_type: Type,
+    /// Debug register slot (0-3) holding the address
+    pub slot: usize,
+    pub enabled: bool,
+    pub hit_count: usize,
+    /// Contents of the watched location when it was last checked
+    pub old_value: Vec<u8>,
+    /// Set if the expression uses local variables: the watchpoint is deleted once their frame
+    /// returns
+    pub scope: Option<WatchpointScope>,
+}
+
+/// The frame whose locals a watchpoint's expression uses.
+#[derive(Debug, Clone)]
+pub struct WatchpointScope {
+    /// Thread the frame belongs to
+    pub tid: Pid,
+    /// Where the frame returns to in its caller
+    pub return_addr: usize,
+    /// rsp is back at the frame's CFA once it has returned
+    pub cfa: usize,
+}
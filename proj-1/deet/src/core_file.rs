@@ -0,0 +1,386 @@
+use crate::inferior::ProgramState;
+use nix::errno::Errno;
+use nix::unistd::Pid;
+use std::convert::TryInto;
+use std::fs;
+use std::mem::size_of;
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_PRFPREG: u32 = 2;
+const NT_PRPSINFO: u32 = 3;
+const NT_AUXV: u32 = 6;
+const NT_SIGINFO: u32 = 0x5349_4749;
+
+/// Offsets into struct elf_prstatus on x86-64
+const PRSTATUS_CURSIG: usize = 12;
+const PRSTATUS_PID: usize = 32;
+const PRSTATUS_REGS: usize = 112;
+/// Offset of pr_psargs (the command line) in struct elf_prpsinfo
+const PRPSINFO_PSARGS: usize = 56;
+const PSARGS_LEN: usize = 80;
+/// Offset of si_addr in siginfo_t
+const SIGINFO_ADDR: usize = 16;
+
+/// A PT_LOAD segment: memory of the process at the time of the crash.
+#[derive(Debug)]
+struct Segment {
+    vaddr: usize,
+    offset: usize,
+    /// Number of bytes saved in the core. Code mapped from files usually isn't, so this can be
+    /// less than the size of the mapping.
+    file_size: usize,
+}
+
+/// A thread of the dumped process, with the registers saved in its NT_PRSTATUS note.
+struct CoreThread {
+    num: usize,
+    tid: Pid,
+    regs: libc::user_regs_struct,
+    fpregs: Option<libc::user_fpregs_struct>,
+}
+
+/// An ELF core dump, inspected like a process that is stopped forever.
+pub struct CoreFile {
+    data: memmap::Mmap,
+    segments: Vec<Segment>,
+    threads: Vec<CoreThread>,
+    /// Index of the current thread in `threads`
+    current: usize,
+    /// Signal that terminated the process
+    signal: i32,
+    /// si_addr of the signal
+    signal_addr: Option<usize>,
+    /// Command line of the process (truncated to 80 bytes by the kernel)
+    command: String,
+    auxv: Vec<u8>,
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(
+        data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> Option<usize> {
+    Some(u64::from_le_bytes(data.get(offset..offset.checked_add(8)?)?.try_into().ok()?) as usize)
+}
+
+/// Reads a C struct of plain integers out of `data`.
+fn read_struct<T: Copy>(data: &[u8], offset: usize) -> Option<T> {
+    let bytes = data.get(offset..offset.checked_add(size_of::<T>())?)?;
+    Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+}
+
+/// Rounds `n` up to the 4 byte alignment of note names and descriptors.
+fn align_note(n: usize) -> Option<usize> {
+    Some(n.checked_add(3)? & !3)
+}
+
+impl CoreFile {
+    /// Loads the x86-64 ELF core dump at `path`.
+    pub fn open(path: &str) -> Result<CoreFile, String> {
+        let file = fs::File::open(path).or(Err(format!("{}: No such file or directory.", path)))?;
+        let data = unsafe { memmap::Mmap::map(&file) }
+            .or(Err(format!("Could not read core file {}", path)))?;
+        let mut core = CoreFile {
+            data,
+            segments: Vec::new(),
+            threads: Vec::new(),
+            current: 0,
+            signal: 0,
+            signal_addr: None,
+            command: String::new(),
+            auxv: Vec::new(),
+        };
+        core.parse().ok_or(format!(
+            "\"{}\" is not a core dump: file format not recognized",
+            path
+        ))?;
+        if core.threads.is_empty() {
+            return Err(format!("Core file {} has no registers", path));
+        }
+        Ok(core)
+    }
+
+    /// Reads the program headers and notes. Returns None if this isn't a 64 bit little endian
+    /// core.
+    fn parse(&mut self) -> Option<()> {
+        let data = &self.data[..];
+        // ELFCLASS64, ELFDATA2LSB
+        if data.get(0..6)? != b"\x7fELF\x02\x01" || read_u16(data, 16)? != ET_CORE {
+            return None;
+        }
+        let phoff = read_u64(data, 32)?;
+        let phentsize = read_u16(data, 54)? as usize;
+        let phnum = read_u16(data, 56)? as usize;
+        let mut notes = Vec::new();
+        for i in 0..phnum {
+            // the headers come from the file, so they may point anywhere
+            let header = phoff.checked_add(i.checked_mul(phentsize)?)?;
+            let offset = read_u64(data, header.checked_add(8)?)?;
+            let file_size = read_u64(data, header.checked_add(32)?)?;
+            match read_u32(data, header)? {
+                PT_LOAD => self.segments.push(Segment {
+                    vaddr: read_u64(data, header.checked_add(16)?)?,
+                    offset,
+                    file_size,
+                }),
+                PT_NOTE => notes.push((offset, file_size)),
+                _ => {}
+            }
+        }
+        for (offset, size) in notes {
+            self.parse_notes(offset, size)?;
+        }
+        Some(())
+    }
+
+    /// Reads the notes in the PT_NOTE segment at `offset`. Each thread has an NT_PRSTATUS note,
+    /// followed by its other notes (NT_PRFPREG); the thread that crashed comes first.
+    fn parse_notes(&mut self, offset: usize, size: usize) -> Option<()> {
+        let data = &self.data[..];
+        let end = offset.checked_add(size)?;
+        let mut pos = offset;
+        while pos.checked_add(12)? <= end {
+            let name_size = read_u32(data, pos)? as usize;
+            let desc_size = read_u32(data, pos + 4)? as usize;
+            let note_type = read_u32(data, pos + 8)?;
+            let desc_start = (pos + 12).checked_add(align_note(name_size)?)?;
+            let desc = data.get(desc_start..desc_start.checked_add(desc_size)?)?;
+            pos = desc_start.checked_add(align_note(desc_size)?)?;
+            match note_type {
+                NT_PRSTATUS => {
+                    if self.threads.is_empty() {
+                        self.signal = read_u16(desc, PRSTATUS_CURSIG)? as i32;
+                    }
+                    self.threads.push(CoreThread {
+                        num: self.threads.len() + 1,
+                        tid: Pid::from_raw(read_u32(desc, PRSTATUS_PID)? as i32),
+                        regs: read_struct(desc, PRSTATUS_REGS)?,
+                        fpregs: None,
+                    });
+                }
+                NT_PRFPREG => {
+                    if let Some(thread) = self.threads.last_mut() {
+                        thread.fpregs = read_struct(desc, 0);
+                    }
+                }
+                NT_PRPSINFO => {
+                    let psargs = desc.get(PRPSINFO_PSARGS..PRPSINFO_PSARGS + PSARGS_LEN)?;
+                    let len = psargs.iter().position(|b| *b == 0).unwrap_or(PSARGS_LEN);
+                    self.command = String::from_utf8_lossy(&psargs[..len])
+                        .trim_end()
+                        .to_string();
+                }
+                NT_AUXV => self.auxv = desc.to_vec(),
+                NT_SIGINFO => self.signal_addr = read_u64(desc, SIGINFO_ADDR),
+                _ => {}
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the signal that terminated the process.
+    pub fn signal(&self) -> i32 {
+        self.signal
+    }
+
+    /// Returns the command line the process was started with.
+    pub fn command(&self) -> &str {
+        &self.command
+    }
+}
+
+impl ProgramState for CoreFile {
+    fn pid(&self) -> Pid {
+        self.threads[0].tid
+    }
+
+    fn tid(&self) -> Pid {
+        self.threads[self.current].tid
+    }
+
+    fn thread_list(&self) -> Vec<(usize, Pid)> {
+        self.threads
+            .iter()
+            .map(|thread| (thread.num, thread.tid))
+            .collect()
+    }
+
+    fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.iter().position(|thread| thread.num == num) {
+            Some(pos) => {
+                self.current = pos;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn current_thread_num(&self) -> usize {
+        self.threads[self.current].num
+    }
+
+    /// Only memory saved in the core can be read; the caller can fall back to the executable
+    /// for code. Fails unless all `len` bytes were saved.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let segment = self
+            .segments
+            .iter()
+            .find(|seg| seg.vaddr <= addr && addr - seg.vaddr < seg.file_size)
+            .ok_or(nix::Error::Sys(Errno::EIO))?;
+        let in_segment = addr - segment.vaddr;
+        let start = segment
+            .offset
+            .checked_add(in_segment)
+            .ok_or(nix::Error::Sys(Errno::EIO))?;
+        let end = start
+            .checked_add(len.min(segment.file_size - in_segment))
+            .ok_or(nix::Error::Sys(Errno::EIO))?;
+        let bytes = self
+            .data
+            .get(start..end)
+            .ok_or(nix::Error::Sys(Errno::EIO))?;
+        let mut bytes = bytes.to_vec();
+        // the rest has to be in the next segment; a gap means we can't read it all
+        if bytes.len() < len {
+            let next = addr
+                .checked_add(bytes.len())
+                .ok_or(nix::Error::Sys(Errno::EIO))?;
+            bytes.extend(self.read_memory(next, len - bytes.len())?);
+        }
+        Ok(bytes)
+    }
+
+    fn get_regs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        Ok(self.threads[self.current].regs)
+    }
+
+    fn read_xmm(&self, xmm: usize) -> Option<u64> {
+        let fpregs = self.threads[self.current].fpregs?;
+        let low = fpregs.xmm_space[xmm * 4] as u64;
+        let high = fpregs.xmm_space[xmm * 4 + 1] as u64;
+        Some(low | (high << 32))
+    }
+
+    fn fault_address(&self) -> Option<usize> {
+        if self.signal != libc::SIGSEGV && self.signal != libc::SIGBUS {
+            return None;
+        }
+        self.signal_addr
+    }
+    fn auxv(&self) -> Option<Vec<u8>> {
+        Some(self.auxv.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Makes a core with the given contents and PT_LOAD segments, without any threads.
+    fn test_core(contents: &[u8], segments: Vec<Segment>) -> CoreFile {
+        let mut data = memmap::MmapMut::map_anon(contents.len().max(1)).unwrap();
+        data[..contents.len()].copy_from_slice(contents);
+        CoreFile {
+            data: data.make_read_only().unwrap(),
+            segments,
+            threads: Vec::new(),
+            current: 0,
+            signal: 0,
+            signal_addr: None,
+            command: String::new(),
+            auxv: Vec::new(),
+        }
+    }
+
+    /// An ELF core header whose single program header is at `phoff`.
+    fn core_header(phoff: u64) -> Vec<u8> {
+        let mut header = vec![0u8; 64];
+        header[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        header[16..18].copy_from_slice(&ET_CORE.to_le_bytes());
+        header[32..40].copy_from_slice(&phoff.to_le_bytes());
+        header[54..56].copy_from_slice(&56u16.to_le_bytes());
+        header[56..58].copy_from_slice(&1u16.to_le_bytes());
+        header
+    }
+
+    #[test]
+    fn test_read_memory() {
+        let contents: Vec<u8> = (0..32).collect();
+        let core = test_core(
+            &contents,
+            vec![
+                Segment {
+                    vaddr: 0x1000,
+                    offset: 0,
+                    file_size: 8,
+                },
+                Segment {
+                    vaddr: 0x1008,
+                    offset: 16,
+                    file_size: 8,
+                },
+                Segment {
+                    vaddr: 0x2000,
+                    offset: 24,
+                    file_size: 8,
+                },
+            ],
+        );
+        assert_eq!(core.read_memory(0x1002, 4).unwrap(), vec![2, 3, 4, 5]);
+        // continues in the adjacent segment
+        assert_eq!(core.read_memory(0x1006, 4).unwrap(), vec![6, 7, 16, 17]);
+        // runs into the gap after the second segment
+        assert!(core.read_memory(0x100c, 8).is_err());
+        assert!(core.read_memory(0x1800, 1).is_err());
+    }
+
+    #[test]
+    fn test_read_memory_overflow() {
+        let core = test_core(
+            &[0; 16],
+            vec![
+                Segment {
+                    vaddr: 0x1000,
+                    offset: usize::MAX - 4,
+                    file_size: 0x100,
+                },
+                Segment {
+                    vaddr: usize::MAX - 8,
+                    offset: 0,
+                    file_size: 8,
+                },
+            ],
+        );
+        assert!(core.read_memory(0x1000, 16).is_err());
+        assert!(core.read_memory(usize::MAX - 4, 16).is_err());
+    }
+
+    #[test]
+    fn test_parse_overflow() {
+        // program header offset that wraps around
+        let mut core = test_core(&core_header(u64::MAX - 8), Vec::new());
+        assert!(core.parse().is_none());
+
+        // a PT_NOTE segment that runs past the end of the address space
+        let mut contents = core_header(64);
+        let mut phdr = vec![0u8; 56];
+        phdr[..4].copy_from_slice(&PT_NOTE.to_le_bytes());
+        phdr[8..16].copy_from_slice(&(u64::MAX - 4).to_le_bytes());
+        phdr[32..40].copy_from_slice(&0x100u64.to_le_bytes());
+        contents.extend(phdr);
+        let mut core = test_core(&contents, Vec::new());
+        assert!(core.parse().is_none());
+    }
+}
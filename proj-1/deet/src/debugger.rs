@@ -1,17 +1,65 @@
-use crate::debugger_command::DebuggerCommand;
-use crate::inferior::Inferior;
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::breakpoint::{Breakpoint, InternalBreakpoint, Watchpoint, WatchpointScope};
+use crate::core_file::CoreFile;
+use crate::debugger_command::{self, DebuggerCommand};
+use crate::disassemble::{self, MAX_INSTRUCTION_LEN};
+use crate::examine::{self, ExamineFormat};
+use crate::expression::{self, Environment, Value};
+use crate::registers;
+use crate::shared_library;
+use crate::signals::{self, SignalTable};
+use crate::inferior::{FollowForkMode, FrameContext, Inferior, ProgramState, OPTIMIZED_OUT};
+use crate::dwarf_data::{
+    DwarfData, Error as DwarfError, FrameState, Function, Line, Place, Type, Variable,
+};
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
-use crate::inferior::Status;
+use crate::inferior::{Frame, Status, NUM_WATCHPOINT_SLOTS};
+use crate::source::{self, ListPosition};
+use std::convert::TryFrom;
+use std::fs;
+use std::mem::size_of;
 
 pub struct Debugger {
     target: String,
     history_path: String,
     readline: Editor<()>,
     inferior: Option<Inferior>,
+    /// Core dump being examined, when no process is running
+    core: Option<CoreFile>,
     debug_data: DwarfData,
-    breakpoints: Vec<usize>,
+    breakpoints: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+    next_breakpoint_num: usize,
+    /// Frame that print/info locals/list operate on, counted from the innermost one
+    selected_frame: usize,
+    /// Where a bare "list" continues; None lists around the selected frame
+    list_position: Option<ListPosition>,
+    /// Where a bare "x" continues
+    next_examine_addr: Option<usize>,
+    /// Number of the current inferior, as shown by "info inferiors"
+    inferior_num: usize,
+    next_inferior_num: usize,
+    /// Forked processes kept stopped in the background (with detach-on-fork off)
+    other_inferiors: Vec<(usize, Inferior)>,
+    follow_fork_mode: FollowForkMode,
+    detach_on_fork: bool,
+    /// Whether new processes are started with address space randomization turned off
+    disable_randomization: bool,
+    /// What to do with each signal the inferior receives ("handle")
+    signal_table: SignalTable,
+    /// Where the inferior stops when its list of shared libraries may have changed: the entry
+    /// point until the dynamic linker is done, then its r_brk hook (for dlopen/dlclose)
+    solib_event_addr: Option<usize>,
+}
+
+/// Splits a location like "file.c:42" into the file and the rest.
+fn split_location(location: &str) -> (Option<&str>, &str) {
+    match location.rfind(':') {
+        Some(pos) => (Some(&location[..pos]), &location[pos + 1..]),
+        None => (None, location),
+    }
 }
 
 fn _parse_address(addr: &str) -> Option<usize> {
@@ -49,19 +97,153 @@ impl Debugger {
         Debugger {
             target: target.to_string(),
             breakpoints: vec![],
+            watchpoints: vec![],
+            next_breakpoint_num: 0,
+            selected_frame: 0,
+            list_position: None,
+            next_examine_addr: None,
+            inferior_num: 1,
+            next_inferior_num: 2,
+            other_inferiors: vec![],
+            follow_fork_mode: FollowForkMode::Parent,
+            detach_on_fork: true,
+            disable_randomization: false,
+            signal_table: SignalTable::new(),
+            solib_event_addr: None,
             history_path,
             readline,
             inferior: None,
+            core: None,
             debug_data,
         }
     }
 
-    pub fn reset(&mut self){
+    pub fn reset(&mut self) {
+        // the breakpoint table outlives the inferior, so it is kept for the next run. Watchpoints
+        // on locals point into the old process's stack though.
+        for wp in self.watchpoints.iter().filter(|wp| wp.scope.is_some()) {
+            print_watchpoint_out_of_scope(wp);
+        }
+        self.watchpoints.retain(|wp| wp.scope.is_none());
         self.inferior = None;
-        self.breakpoints = vec![];
+        self.selected_frame = 0;
+        self.list_position = None;
+    }
+
+    /// Attaches to a running process, loading its debugging symbols first if it runs a
+    /// different binary than the current target.
+    pub fn attach(&mut self, pid: i32) {
+        let exe = match fs::read_link(format!("/proc/{}/exe", pid)) {
+            Ok(exe) => exe.to_string_lossy().to_string(),
+            Err(_) => {
+                println!("Err: no such process {}", pid);
+                return;
+            }
+        };
+        self.clear_shared_libraries();
+        if let Err(e) = self.load_symbols(&exe) {
+            println!("Err: could not load debugging symbols from {}: {:?}", exe, e);
+            return;
+        }
+        match Inferior::attach(Pid::from_raw(pid)) {
+            Some(inferior) => {
+                println!("Attaching to process {}", pid);
+                self.inferior = Some(inferior);
+                self.core = None;
+                self.configure_inferiors();
+                self.apply_load_bias();
+                self.load_shared_libraries();
+                self.insert_breakpoints();
+                self.arm_watchpoints();
+                let rip = self.inferior.as_ref().unwrap().get_rip().unwrap_or(0);
+                match self.debug_data.get_function_from_addr(rip) {
+                    Some(func) => println!("Stopped in {} ({:#x})", func, rip),
+                    None => println!("Stopped at {:#x}", rip),
+                }
+            }
+            None => println!("Err: could not attach to process {}", pid),
+        }
+    }
+
+    /// Opens the core dump at `path` and shows where the process died.
+    pub fn load_core(&mut self, path: &str) {
+        let core = match CoreFile::open(path) {
+            Ok(core) => core,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        println!("Core was generated by `{}'.", core.command());
+        match Signal::try_from(core.signal()) {
+            Ok(signal) => println!(
+                "Program terminated with signal {:?}, {}.",
+                signal,
+                signals::description(signal)
+            ),
+            Err(_) => println!("Program terminated with signal {}.", core.signal()),
+        }
+        self.core = Some(core);
+        self.apply_load_bias();
+        self.load_shared_libraries();
+        self.select_frame(0);
+        if let Some(line) = self.get_current_line() {
+            if let Some(text) = source::get_line(&line.file, line.number) {
+                println!("{}\t{}", line.number, text);
+            }
+        }
+    }
+
+    /// Returns the process being debugged, or else the core dump being examined.
+    fn program(&self) -> Option<&dyn ProgramState> {
+        match self.inferior {
+            Some(ref inferior) => Some(inferior),
+            None => self.core.as_ref().map(|core| core as &dyn ProgramState),
+        }
+    }
+
+    fn program_mut(&mut self) -> Option<&mut dyn ProgramState> {
+        match self.inferior {
+            Some(ref mut inferior) => Some(inferior),
+            None => self.core.as_mut().map(|core| core as &mut dyn ProgramState),
+        }
+    }
+
+    /// Switches the debugging symbols to those of the program `exe`, unless they are loaded
+    /// already. Returns whether they changed.
+    fn load_symbols(&mut self, exe: &str) -> Result<bool, DwarfError> {
+        let same_target = fs::canonicalize(&self.target)
+            .map(|target| target.to_string_lossy() == exe)
+            .unwrap_or(false);
+        if same_target {
+            return Ok(false);
+        }
+        self.debug_data = DwarfData::from_file(exe)?;
+        println!("Reading symbols from {}", exe);
+        self.target = exe.to_string();
+        Ok(true)
     }
 
-    pub fn match_res(&mut self, res: Result<Status,nix::Error>){
+    /// Removes all breakpoints from the inferior and lets it run on its own.
+    fn detach(&mut self) {
+        let inferior = self.inferior.as_mut().unwrap();
+        let pid = inferior.pid();
+        match inferior.detach() {
+            Ok(()) => println!("Detaching from process {}", pid),
+            Err(e) => println!("Err: detaching from process {} failed: {:?}", pid, e),
+        }
+        self.reset();
+    }
+
+    pub fn match_res(&mut self, res: Result<Status, nix::Error>) {
+        // the inferior moved, so go back to the innermost frame
+        self.selected_frame = 0;
+        self.list_position = None;
+        self.hand_over_strays();
+        if let Ok(ref status) = res {
+            self.follow_process_event(status);
+            self.delete_out_of_scope_watchpoints();
+        }
         match res {
             Ok(v) => {
                 match v {
@@ -71,12 +253,27 @@ impl Debugger {
                     }
                     Status::Stopped(_signal,_rip) => {
                         println!("Child stopped (signal {:?})",_signal);
-                        if let Some(rip_line) = self.debug_data.get_line_from_addr(_rip){
-                            println!("Stopped at {}:{}",rip_line.file, rip_line.number);
+                        if let Some(addr) = self.inferior.as_ref().unwrap().fault_address() {
+                            println!("{} at address {:#x}", signals::description(_signal), addr);
                         }
+                        self.print_stop_location(_rip);
                     }
-                    _ => {
-                        println!("Child send unknown information");
+                    Status::Signaled(signal) => {
+                        self.reset();
+                        println!("Child terminated (signal {:?})", signal);
+                    }
+                    Status::Watchpoint(slot, rip) => {
+                        self.report_watchpoint(slot);
+                        self.print_stop_location(rip);
+                    }
+                    Status::Forked(_) => {
+                        // we are in the child now
+                        if let Ok(rip) = self.inferior.as_ref().unwrap().get_rip() {
+                            self.print_stop_location(rip);
+                        }
+                    }
+                    Status::Execed(rip) => {
+                        self.print_stop_location(rip);
                     }
                 }
             }
@@ -86,11 +283,27 @@ impl Debugger {
         }
     }
 
+    /// Prints the file:line the inferior stopped at, along with that line of source.
+    fn print_stop_location(&self, rip: usize) {
+        if let Some(rip_line) = self.debug_data.get_line_from_addr(rip){
+            println!("Stopped at {}:{}",rip_line.file, rip_line.number);
+            if let Some(text) = source::get_line(&rip_line.file, rip_line.number) {
+                println!("{}\t{}", rip_line.number, text);
+            }
+        } else if let Some(location) = self.debug_data.get_symbol_location(rip) {
+            println!("Stopped at {:#x} in {}", rip, location);
+        }
+    }
+
     pub fn run(&mut self) {
         loop {
-            match self.get_next_command() {
+            let cmd = self.get_next_command();
+            match cmd {
                 DebuggerCommand::Run(args) => {
-                    if self.inferior.is_some(){
+                    if self.inferior.as_ref().map_or(false, |inferior| inferior.is_attached()) {
+                        // don't kill a process we only attached to, leave it running like quit
+                        self.detach();
+                    } else if self.inferior.is_some(){
                         //1. first kill n reap it
                         self.inferior
                             .as_mut()
@@ -99,20 +312,35 @@ impl Debugger {
                         //2. finally clean it
                         self.reset();
                     }
-                    if let Some(inferior) = Inferior::new(&self.target, &args, &self.breakpoints) {
+                    self.kill_other_inferiors();
+                    // a live process replaces the core dump
+                    self.core = None;
+                    // the libraries may be loaded elsewhere this time
+                    self.clear_shared_libraries();
+                    if let Some(inferior) =
+                        Inferior::new(&self.target, &args, self.disable_randomization)
+                    {
                         // Create the inferior
                         self.inferior = Some(inferior);
+                        self.configure_inferiors();
+                        self.apply_load_bias();
+                        self.load_shared_libraries();
+                        self.insert_breakpoints();
+                        self.arm_watchpoints();
                         // TODO (milestone 1): make the inferior run
                         // You may use self.inferior.as_mut().unwrap() to get a mutable reference
                         // to the Inferior object
-                        let cont_res = self.inferior.as_mut().unwrap().cont();
+                        let cont_res = self.continue_inferior();
                         self.match_res(cont_res);
                     } else {
                         println!("Error starting subprocess");
                     }
                 }
                 DebuggerCommand::Quit => {
-                    if self.inferior.is_some(){
+                    if self.inferior.as_ref().map_or(false, |inferior| inferior.is_attached()) {
+                        // leave processes we attached to running, like gdb does
+                        self.detach();
+                    } else if self.inferior.is_some(){
                         //1. first kill n reap it
                         self.inferior
                             .as_mut()
@@ -121,54 +349,308 @@ impl Debugger {
                         //2. finally clean it
                         self.reset();
                     }
+                    self.kill_other_inferiors();
                     return;
                 }
+                DebuggerCommand::Attach(pid) => {
+                    if let Some(ref inferior) = self.inferior {
+                        println!(
+                            "Err: already debugging process {}, detach from it first",
+                            inferior.pid()
+                        );
+                    } else {
+                        match pid.parse::<i32>() {
+                            Ok(pid) => self.attach(pid),
+                            Err(_) => println!("<usage>: attach <pid>"),
+                        }
+                    }
+                }
+                DebuggerCommand::Detach => {
+                    if let None = self.inferior{
+                        println!("Err: no process is running yet");
+                    } else {
+                        self.detach();
+                    }
+                }
                 DebuggerCommand::Cont => {
                     // 1. check whether process is running
                     if let None = self.inferior{
                         println!("Err: no process is running yet");
                     } else{
                         // 2. resume the child
-                        let my_continue_res = self.inferior.as_mut().unwrap().cont();
+                        let my_continue_res = self.continue_inferior();
                         self.match_res(my_continue_res);
                     }
                 }
                 DebuggerCommand::Back => {
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        if let Err(e) = self.program().unwrap().backtrace(&self.debug_data) {
+                            println!("Err: could not unwind the stack: {:?}", e);
+                        }
+                    }
+                }
+                DebuggerCommand::Up(ref count) | DebuggerCommand::Down(ref count) => {
+                    let count = match count.as_ref().map(|c| c.parse::<usize>()) {
+                        None => Ok(1),
+                        Some(res) => res,
+                    };
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else if let Ok(count) = count {
+                        let up = matches!(cmd, DebuggerCommand::Up(_));
+                        self.move_frame(up, count);
+                    } else {
+                        println!("<usage>: up/down [count]");
+                    }
+                }
+                DebuggerCommand::Frame(num) => {
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        match num.map(|num| num.parse::<usize>()) {
+                            None => self.select_frame(self.selected_frame),
+                            Some(Ok(num)) => self.select_frame(num),
+                            Some(Err(_)) => println!("<usage>: frame [num]"),
+                        }
+                    }
+                }
+                DebuggerCommand::StepInstruction => {
                     if let None = self.inferior{
                         println!("Err: no process is running yet");
                     } else {
-                        self.inferior.as_mut().unwrap().backtrace(&self.debug_data);
+                        let step_res = self.step_instruction();
+                        if let Ok(Status::Stopped(nix::sys::signal::SIGTRAP, rip)) = step_res {
+                            if self.is_user_breakpoint(rip) {
+                                // we stop anyway, but the hit counts (and a tbreak is used up)
+                                self.breakpoint_hit(rip);
+                            }
+                        }
+                        self.match_res(step_res);
                     }
                 }
-                DebuggerCommand::BreakPoint(args) => {
-                    if args.len() > 1{
-                        println!("<usage>: b/break *addr/symbol");
-                    } else{
-                        let addr = &args[0];
-                        if addr.starts_with("*"){
-                            if let Some(parse_res) = _parse_address(&addr[1..]){
-                                self.add_breakpint(parse_res);
+                DebuggerCommand::Next | DebuggerCommand::Step => {
+                    if let None = self.inferior{
+                        println!("Err: no process is running yet");
+                    } else {
+                        let step_into = matches!(cmd, DebuggerCommand::Step);
+                        let step_res = self.step_line(step_into);
+                        self.match_res(step_res);
+                    }
+                }
+                DebuggerCommand::Finish => {
+                    if let None = self.inferior{
+                        println!("Err: no process is running yet");
+                    } else {
+                        // finish returns from the selected frame, which "up" may have moved
+                        let frame = self.get_selected_frame();
+                        let func = frame.as_ref().and_then(|frame| {
+                            self.debug_data
+                                .get_function_from_addr(frame.lookup_addr(self.selected_frame == 0))
+                        });
+                        let has_caller = self
+                            .program()
+                            .unwrap()
+                            .unwind(&self.debug_data)
+                            .map_or(false, |frames| frames.len() > self.selected_frame + 1);
+                        if !has_caller || func.as_deref() == Some("main") {
+                            println!("\"finish\" not meaningful in the outermost frame.");
+                        } else {
+                            println!("Run till exit from {}", func.unwrap_or("??".to_string()));
+                            let finish_res = self.finish(self.selected_frame);
+                            self.match_res(finish_res);
+                        }
+                    }
+                }
+                DebuggerCommand::Until(ref location) | DebuggerCommand::Advance(ref location) => {
+                    let until = matches!(cmd, DebuggerCommand::Until(_));
+                    if self.inferior.is_none() {
+                        println!("Err: no process is running yet");
+                    } else if location.is_empty() {
+                        println!("<usage>: {} <location>", if until { "until" } else { "advance" });
+                    } else {
+                        self.run_to_location(location, until);
+                    }
+                }
+                DebuggerCommand::List(arg) => {
+                    self.list(arg);
+                }
+                DebuggerCommand::Disassemble(arg) => {
+                    self.disassemble(arg);
+                }
+                DebuggerCommand::Examine(format, addr) => {
+                    self.examine(&format, &addr);
+                }
+                DebuggerCommand::Print(name) => {
+                    if name.is_empty() {
+                        println!("<usage>: p/print <variable>");
+                    } else if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        self.print_expression(&name);
+                    }
+                }
+                DebuggerCommand::InfoLocals => {
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        self.print_locals();
+                    }
+                }
+                DebuggerCommand::InfoGlobals => {
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        match self.selected_context() {
+                            Some(context) => {
+                                for var in self.debug_data.global_variables() {
+                                    let place = var.location.evaluate(&context);
+                                    println!(
+                                        "{} = {}",
+                                        var.name,
+                                        self.read_variable(&context, var, &place)
+                                    );
+                                }
+                            }
+                            None => println!("Err: could not unwind the stack"),
+                        }
+                    }
+                }
+                DebuggerCommand::InfoBreakpoints => {
+                    self.print_breakpoints();
+                }
+                DebuggerCommand::InfoInferiors => {
+                    self.print_inferiors();
+                }
+                DebuggerCommand::Handle(args) => {
+                    self.handle_signal(&args);
+                }
+                DebuggerCommand::InfoSignals(name) => {
+                    self.print_signals(name);
+                }
+                DebuggerCommand::InfoSharedLibrary => {
+                    self.print_shared_libraries();
+                }
+                DebuggerCommand::InfoThreads => {
+                    if self.program().is_none() {
+                        println!("No threads.");
+                    } else {
+                        self.print_threads();
+                    }
+                }
+                DebuggerCommand::Thread(args) => {
+                    if self.program().is_none() {
+                        println!("Err: no process is running yet");
+                    } else {
+                        self.thread(&args);
+                    }
+                }
+                DebuggerCommand::Inferior(num) => match num.parse::<usize>() {
+                    Ok(num) => self.switch_inferior(num),
+                    Err(_) => println!("<usage>: inferior <num>"),
+                },
+                DebuggerCommand::InfoRegisters(name) => {
+                    if self.program().is_none() {
+                        println!("The program has no registers now.");
+                    } else {
+                        self.print_registers(name);
+                    }
+                }
+                DebuggerCommand::Set(expr) => {
+                    self.set(&expr);
+                }
+                DebuggerCommand::Delete(args) => {
+                    if let Some(nums) = self.parse_breakpoint_nums(&args) {
+                        for num in nums {
+                            if let Some(pos) = self.breakpoints.iter().position(|bp| bp.num == num) {
+                                let bp = self.breakpoints.remove(pos);
+                                for addr in bp.addrs {
+                                    self.sync_breakpoint(addr);
+                                }
+                            } else if let Some(pos) = self.watchpoints.iter().position(|wp| wp.num == num) {
+                                let wp = self.watchpoints.remove(pos);
+                                self.sync_watchpoint(&wp, false);
+                            }
+                        }
+                    }
+                }
+                DebuggerCommand::Disable(ref args) | DebuggerCommand::Enable(ref args) => {
+                    let enabled = matches!(cmd, DebuggerCommand::Enable(_));
+                    if let Some(nums) = self.parse_breakpoint_nums(args) {
+                        for num in nums {
+                            if let Some(bp) = self.breakpoints.iter_mut().find(|bp| bp.num == num) {
+                                bp.enabled = enabled;
+                                for addr in bp.addrs.clone() {
+                                    self.sync_breakpoint(addr);
+                                }
+                            } else if let Some(wp) = self.watchpoints.iter_mut().find(|wp| wp.num == num) {
+                                wp.enabled = enabled;
+                                let wp = wp.clone();
+                                self.sync_watchpoint(&wp, enabled);
                             }
-                        } else{
-                            let addr = &args[0];
-                            // 1. if it's function name
-                            if let Some(parse_res) = self.debug_data.get_addr_for_function(None, addr){
-                                self.add_breakpint(parse_res);
-                            } else {
-                                // 2. if it can be a line number
-                                match addr.parse::<usize>().ok(){
-                                    Some(addr_u) => {
-                                        if let Some(parse_res) = self.debug_data.get_addr_for_line(None, addr_u){
-                                            self.add_breakpint(parse_res);
-                                        } else{
-                                            println!("fail to parse addr {} as function or usize",&args[0]);
-                                        }
-                                    }
-                                    None => {
-                                        println!("fail to parse addr {} as function or usize",&args[0]);
-                                    }
+                        }
+                    }
+                }
+                DebuggerCommand::Watch(expr) => {
+                    if expr.is_empty() {
+                        println!("<usage>: watch <variable>/*addr");
+                    } else if let None = self.inferior{
+                        println!("Err: no process is running yet");
+                    } else {
+                        self.add_watchpoint(&expr);
+                    }
+                }
+                DebuggerCommand::Ignore(args) => {
+                    let parsed = match args.as_slice() {
+                        [num, count] => num.parse::<usize>().ok().zip(count.parse::<usize>().ok()),
+                        _ => None,
+                    };
+                    match parsed {
+                        Some((num, count)) => {
+                            match self.breakpoints.iter_mut().find(|bp| bp.num == num) {
+                                Some(bp) => {
+                                    bp.ignore_count = count;
+                                    println!("Will ignore next {} crossings of breakpoint {}.", count, num);
                                 }
+                                None => println!("No breakpoint number {}.", num),
+                            }
+                        }
+                        None => println!("<usage>: ignore <breakpoint num> <count>"),
+                    }
+                }
+                DebuggerCommand::BreakPoint(ref args) | DebuggerCommand::TempBreakPoint(ref args) => {
+                    let temporary = matches!(cmd, DebuggerCommand::TempBreakPoint(_));
+                    if args.is_empty() || (args.len() > 1 && (args[1] != "if" || args.len() == 2)) {
+                        println!("<usage>: b/break/tbreak *addr/symbol [if condition]");
+                    } else{
+                        let condition = if args.len() > 2 {
+                            Some(args[2..].join(" "))
+                        } else {
+                            None
+                        };
+                        let addrs = self.resolve_location(&args[0]);
+                        let (file, spec) = split_location(&args[0]);
+                        if !addrs.is_empty() {
+                            if let Some(bp) = self.add_breakpint(addrs, &args[0]) {
+                                bp.condition = condition;
+                                bp.temporary = temporary;
                             }
+                        } else if let Some(file) =
+                            file.filter(|file| self.debug_data.get_target_file(file).is_none())
+                        {
+                            println!("No source file named {}.", file);
+                        } else if !spec.starts_with('*') && spec.parse::<usize>().is_err() {
+                            println!("Function \"{}\" not defined.", spec);
+                            // the function may be in a shared library that isn't loaded yet,
+                            // unless the program is statically linked
+                            if self.debug_data.get_dynamic_addr().is_some() {
+                                let bp = self.add_pending_breakpoint(&args[0]);
+                                bp.condition = condition;
+                                bp.temporary = temporary;
+                            }
+                        } else {
+                            println!("fail to parse addr {} as function or usize",&args[0]);
                         }
                     }
                 }
@@ -176,56 +658,1978 @@ impl Debugger {
         }
     }
 
-    fn add_breakpint(&mut self, parse_res:usize) {
-        println!("Set breakpoint {} at {:#x}",self.breakpoints.len(),parse_res);
-        self.breakpoints.push(parse_res);
-        // should we check the breakpoint already in the vector?
+    /// Resolves a breakpoint location: "*addr", a function name or a line number, optionally
+    /// prefixed with a file ("file.c:42", "file.c:func"). Returns every address the location
+    /// stands for, or nothing if it can't be found.
+    fn resolve_location(&self, location: &str) -> Vec<usize> {
+        if location.starts_with("*") {
+            return _parse_address(&location[1..]).into_iter().collect();
+        }
+        let (file, spec) = split_location(location);
+        // 1. if it can be a line number
+        if let Ok(line) = spec.parse::<usize>() {
+            // a bare line number is in the file we are looking at
+            let current_file = self.get_current_line().map(|line| line.file);
+            return self
+                .debug_data
+                .get_addrs_for_line(file.or(current_file.as_deref()), line);
+        }
+        // 2. if it's function name. Stop after the prologue so that arguments can be read (and
+        // used in the condition).
+        let addrs = self.debug_data.get_addrs_for_function(file, spec);
+        if !addrs.is_empty() || file.is_some() {
+            return addrs;
+        }
+        // functions without debug info, e.g. in libc
+        self.debug_data
+            .get_addr_for_symbol(spec)
+            .map(|addr| self.debug_data.get_addr_after_prologue(addr))
+            .into_iter()
+            .collect()
+    }
+
+    fn add_breakpint(&mut self, addrs: Vec<usize>, location: &str) -> Option<&mut Breakpoint> {
         if self.inferior.is_some(){
-            if let Err(_) = self.inferior.as_mut().unwrap().append_breakpoint(parse_res){
-                println!("Add breakpoint failed, clean INT at {:#x}",parse_res);
-                self.breakpoints.pop();
+            for (i, &addr) in addrs.iter().enumerate() {
+                if let Err(_) = self.inferior.as_mut().unwrap().append_breakpoint(addr){
+                    println!("Add breakpoint failed, clean INT at {:#x}",addr);
+                    // take out the ones we already wrote, unless other breakpoints use them
+                    for &addr in addrs[..i].iter() {
+                        self.sync_breakpoint(addr);
+                    }
+                    return None;
+                }
             }
         }
+        let num = self.next_breakpoint_num;
+        if addrs.len() == 1 {
+            println!("Set breakpoint {} at {:#x}", num, addrs[0]);
+        } else {
+            let list: Vec<String> = addrs.iter().map(|addr| format!("{:#x}", addr)).collect();
+            println!(
+                "Set breakpoint {} at {} ({} locations)",
+                num,
+                list.join(", "),
+                addrs.len()
+            );
+        }
+        self.next_breakpoint_num += 1;
+        self.breakpoints.push(Breakpoint::new(num, addrs, location));
+        self.breakpoints.last_mut()
     }
 
-    /// This function prompts the user to enter a command, and continues re-prompting until the user
-    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
-    ///
-    /// You don't need to read, understand, or modify this function.
-    fn get_next_command(&mut self) -> DebuggerCommand {
+    /// Adds a breakpoint on `location` that is resolved when a shared library defining it is
+    /// loaded.
+    fn add_pending_breakpoint(&mut self, location: &str) -> &mut Breakpoint {
+        let num = self.next_breakpoint_num;
+        println!("Breakpoint {} ({}) pending.", num, location);
+        self.next_breakpoint_num += 1;
+        let mut bp = Breakpoint::new(num, Vec::new(), location);
+        bp.pending = true;
+        self.breakpoints.push(bp);
+        self.breakpoints.last_mut().unwrap()
+    }
+
+    /// Continues the inferior, silently resuming as long as it only stops at breakpoints that
+    /// don't want to stop (false condition or ignore count left).
+    fn continue_inferior(&mut self) -> Result<Status, nix::Error> {
+        self.continue_inferior_to(&[])
+    }
+
+    /// Like continue_inferior, but also stops at the internal breakpoints `stops`.
+    fn continue_inferior_to(&mut self, stops: &[InternalBreakpoint]) -> Result<Status, nix::Error> {
+        // also stop when a frame that watchpoints depend on returns, so they can be deleted
+        let stops: Vec<InternalBreakpoint> = stops
+            .iter()
+            .cloned()
+            .chain(self.watchpoints.iter().filter_map(|wp| {
+                wp.scope.as_ref().map(|scope| InternalBreakpoint {
+                    addr: scope.return_addr,
+                    min_sp: scope.cfa,
+                })
+            }))
+            .collect();
+        let mut stop_addrs: Vec<usize> = stops.iter().map(|stop| stop.addr).collect();
         loop {
-            // Print prompt and get next line of user input
-            match self.readline.readline("(deet) ") {
-                Err(ReadlineError::Interrupted) => {
-                    // User pressed ctrl+c. We're going to ignore it
-                    println!("Type \"quit\" to exit");
-                }
-                Err(ReadlineError::Eof) => {
-                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
-                    return DebuggerCommand::Quit;
+            // the inferior takes internal breakpoints out whenever it stops
+            let inferior = self.inferior.as_mut().unwrap();
+            inferior.set_internal_breakpoints(&stop_addrs)?;
+            let status = inferior.cont()?;
+            match status {
+                Status::Stopped(nix::sys::signal::SIGTRAP, rip) => {
+                    let solib_event = self.solib_event_addr == Some(rip);
+                    if solib_event {
+                        self.load_shared_libraries();
+                        self.insert_breakpoints();
+                    }
+                    let user_breakpoint = self.is_user_breakpoint(rip);
+                    let internal_stop = if stop_addrs.contains(&rip) {
+                        let rsp = self.inferior.as_ref().unwrap().get_regs()?.rsp as usize;
+                        stops.iter().any(|stop| stop.addr == rip && rsp >= stop.min_sp)
+                    } else {
+                        false
+                    };
+                    if user_breakpoint {
+                        // count the hit even if we stop anyway
+                        if self.breakpoint_hit(rip) || internal_stop {
+                            return Ok(status);
+                        }
+                    } else if internal_stop || !(solib_event || stop_addrs.contains(&rip)) {
+                        return Ok(status);
+                    }
                 }
-                Err(err) => {
-                    panic!("Unexpected I/O error: {:?}", err);
+                // keep going in the new process or program
+                Status::Forked(_) => self.follow_process_event(&status),
+                Status::Execed(_) => {
+                    // the stops belong to the old program
+                    stop_addrs.clear();
+                    self.follow_process_event(&status);
                 }
-                Ok(line) => {
-                    if line.trim().len() == 0 {
-                        continue;
+                _ => return Ok(status),
+            }
+        }
+    }
+
+    /// Executes a single instruction. Stepping onto the dynamic linker's hook means libraries
+    /// were loaded or unloaded, which we pick up like continue does.
+    fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        let status = self.inferior.as_mut().unwrap().step_instruction()?;
+        if let Status::Stopped(nix::sys::signal::SIGTRAP, rip) = status {
+            if self.solib_event_addr == Some(rip) {
+                self.load_shared_libraries();
+                self.insert_breakpoints();
+            }
+        }
+        Ok(status)
+    }
+
+    /// Steps to the next source line. Calls are stepped over unless `step_into` is set and the
+    /// callee has line information, in which case we stop at the first line of its body.
+    /// Breakpoints on the way only stop us if they want to, like with continue.
+    fn step_line(&mut self, step_into: bool) -> Result<Status, nix::Error> {
+        let rip = self.inferior.as_ref().unwrap().get_rip()?;
+        let mut start_line = match self.debug_data.get_line_from_addr(rip) {
+            Some(line) => line,
+            // no line info here (e.g. inside libc), so just get out of this function
+            None => return self.finish(0),
+        };
+        loop {
+            let inferior = self.inferior.as_mut().unwrap();
+            let is_call = inferior.is_call_instruction(inferior.get_rip()?)?;
+            let new_rip = match self.step_instruction()? {
+                Status::Stopped(nix::sys::signal::SIGTRAP, new_rip) => new_rip,
+                other => return Ok(other),
+            };
+            if is_call {
+                match self.debug_data.get_line_from_addr(new_rip) {
+                    Some(callee_line) if step_into => {
+                        // we are on the callee's opening line, stop once we leave it
+                        start_line = callee_line;
                     }
-                    self.readline.add_history_entry(line.as_str());
-                    if let Err(err) = self.readline.save_history(&self.history_path) {
-                        println!(
-                            "Warning: failed to save history file at {}: {}",
-                            self.history_path, err
-                        );
+                    _ => {
+                        let inferior = self.inferior.as_ref().unwrap();
+                        let return_addr = inferior.read_return_address()?;
+                        // once the callee returns, rsp is above the return address again
+                        let stop = InternalBreakpoint {
+                            addr: return_addr,
+                            min_sp: inferior.get_regs()?.rsp as usize + size_of::<usize>(),
+                        };
+                        match self.continue_inferior_to(&[stop])? {
+                            Status::Stopped(nix::sys::signal::SIGTRAP, rip)
+                                if rip == return_addr => {}
+                            other => return Ok(other),
+                        }
                     }
-                    let tokens: Vec<&str> = line.split_whitespace().collect();
-                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
-                        return cmd;
-                    } else {
-                        println!("Unrecognized command.");
+                }
+                continue;
+            }
+            if self.is_user_breakpoint(new_rip) && self.breakpoint_hit(new_rip) {
+                // landed on a breakpoint in the middle of the line
+                return Ok(Status::Stopped(nix::sys::signal::SIGTRAP, new_rip));
+            }
+            match self.debug_data.get_line_from_addr(new_rip) {
+                Some(line) => {
+                    if line.file != start_line.file || line.number != start_line.number {
+                        return Ok(Status::Stopped(nix::sys::signal::SIGTRAP, new_rip));
                     }
                 }
+                // returned into code without debug info (e.g. main returning into libc), so stop
+                // in the caller like finish does
+                None => return Ok(Status::Stopped(nix::sys::signal::SIGTRAP, new_rip)),
+            }
+        }
+    }
+
+    /// Runs until the function of frame `num` returns to its caller.
+    fn finish(&mut self, num: usize) -> Result<Status, nix::Error> {
+        let frames = self.program().unwrap().unwind(&self.debug_data)?;
+        let (frame, caller) = match (frames.get(num), frames.get(num + 1)) {
+            (Some(frame), Some(caller)) => (frame, caller),
+            // can't tell where this function returns to
+            _ => return Err(nix::Error::UnsupportedOperation),
+        };
+        // rsp is back at the CFA once we have returned (so recursive calls don't stop us early)
+        let stop = InternalBreakpoint {
+            addr: caller.rip,
+            min_sp: frame.cfa,
+        };
+        self.continue_inferior_to(&[stop])
+    }
+
+    /// Runs to `location` ("until"/"advance"), stopping early if the selected frame returns.
+    /// "until" doesn't stop at the location in calls made from the selected frame, so that
+    /// recursion doesn't end it early.
+    fn run_to_location(&mut self, location: &str, until: bool) {
+        let addrs = self.resolve_location(location);
+        if addrs.is_empty() {
+            println!("Err: could not resolve location {}", location);
+            return;
+        }
+        let frames = match self.program().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("Err: could not unwind the stack: {:?}", e);
+                return;
+            }
+        };
+        let frame = match frames.get(self.selected_frame) {
+            Some(frame) => frame,
+            None => {
+                println!("Err: could not unwind the stack");
+                return;
+            }
+        };
+        let min_sp = if until { frame.rsp } else { 0 };
+        let mut stops: Vec<InternalBreakpoint> = addrs
+            .into_iter()
+            .map(|addr| InternalBreakpoint { addr, min_sp })
+            .collect();
+        // once the frame returns, rsp is back at its CFA
+        if let Some(caller) = frames.get(self.selected_frame + 1) {
+            stops.push(InternalBreakpoint {
+                addr: caller.rip,
+                min_sp: frame.cfa,
+            });
+        }
+        let res = self.continue_inferior_to(&stops);
+        self.match_res(res);
+    }
+
+    /// Takes over the processes the inferior forked off, and switches to the forked child or to
+    /// the symbols of the exec'd program if `status` says so.
+    fn follow_process_event(&mut self, status: &Status) {
+        let forked = match self.inferior {
+            Some(ref mut inferior) => inferior.take_forked(),
+            None => return,
+        };
+        for child in forked {
+            let num = self.next_inferior_num;
+            self.next_inferior_num += 1;
+            match status {
+                Status::Forked(pid) if *pid == child.pid() => self.follow_child(num, child),
+                _ => {
+                    println!("[New inferior {} (process {})]", num, child.pid());
+                    self.other_inferiors.push((num, child));
+                }
+            }
+        }
+        if let Status::Execed(_) = status {
+            self.follow_exec();
+        }
+    }
+
+    /// Makes the forked `child` the current inferior. The parent is detached, or kept stopped
+    /// with detach-on-fork off.
+    fn follow_child(&mut self, num: usize, child: Inferior) {
+        let mut parent = self.inferior.take().unwrap();
+        println!(
+            "[Attaching after process {} fork to child process {}]",
+            parent.pid(),
+            child.pid()
+        );
+        println!("[New inferior {} (process {})]", num, child.pid());
+        if self.detach_on_fork {
+            println!(
+                "[Detaching after fork from parent process {}]",
+                parent.pid()
+            );
+            match parent.detach() {
+                Ok(()) => println!(
+                    "[Inferior {} (process {}) detached]",
+                    self.inferior_num,
+                    parent.pid()
+                ),
+                Err(e) => println!(
+                    "Err: detaching from process {} failed: {:?}",
+                    parent.pid(),
+                    e
+                ),
+            }
+        } else {
+            self.other_inferiors.push((self.inferior_num, parent));
+        }
+        self.inferior = Some(child);
+        self.inferior_num = num;
+        // debug registers aren't inherited
+        self.arm_watchpoints();
+    }
+
+    /// Loads the symbols of the program the inferior exec'd and sets the breakpoints again in
+    /// its fresh address space.
+    fn follow_exec(&mut self) {
+        let inferior = self.inferior.as_ref().unwrap();
+        let pid = inferior.pid();
+        let exe = inferior.exe().unwrap_or_else(|| self.target.clone());
+        println!("process {} is executing new program: {}", pid, exe);
+        self.clear_shared_libraries();
+        match self.load_symbols(&exe) {
+            Ok(true) => self.relocate_breakpoints(),
+            Ok(false) => {}
+            Err(e) => println!(
+                "Err: could not load debugging symbols from {}: {:?}",
+                exe, e
+            ),
+        }
+        self.apply_load_bias();
+        self.load_shared_libraries();
+        self.insert_breakpoints();
+        self.arm_watchpoints();
+    }
+
+    /// Resolves the breakpoint locations again after the symbols changed. Watchpoints on the old
+    /// program's memory are meaningless now, so they are deleted.
+    fn relocate_breakpoints(&mut self) {
+        let addrs: Vec<Vec<usize>> = self
+            .breakpoints
+            .iter()
+            .map(|bp| self.resolve_location(&bp.location))
+            .collect();
+        for (bp, addrs) in self.breakpoints.iter_mut().zip(addrs) {
+            if bp.pending {
+                continue;
+            }
+            match addrs {
+                addrs if !addrs.is_empty() => bp.addrs = addrs,
+                _ if bp.enabled => {
+                    println!(
+                        "Breakpoint {}: no location \"{}\" in the new program, disabling it",
+                        bp.num, bp.location
+                    );
+                    bp.enabled = false;
+                }
+                _ => {}
+            }
+        }
+        for wp in std::mem::take(&mut self.watchpoints) {
+            println!(
+                "Watchpoint {} deleted: {} belongs to the old program",
+                wp.num, wp.expr
+            );
+        }
+    }
+
+    /// Relocates the symbols to where the current program was loaded, which changes from run to
+    /// run for position independent executables. Breakpoints move along, except those set at an
+    /// explicit address, and so do watchpoints on the program's static data.
+    fn apply_load_bias(&mut self) {
+        let entry = match self.program().and_then(|program| program.entry_point()) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let load_bias = entry.wrapping_sub(self.debug_data.get_entry());
+        let delta = load_bias.wrapping_sub(self.debug_data.get_load_bias());
+        if delta == 0 {
+            return;
+        }
+        // the stack and heap aren't part of the program, so watchpoints there stay put
+        let static_watchpoints: Vec<bool> = self
+            .watchpoints
+            .iter()
+            .map(|wp| self.debug_data.contains_address(wp.addr))
+            .collect();
+        self.debug_data.set_load_bias(load_bias);
+        for bp in self.breakpoints.iter_mut() {
+            if !bp.pending && !bp.location.starts_with('*') {
+                for addr in bp.addrs.iter_mut() {
+                    *addr = addr.wrapping_add(delta);
+                }
             }
         }
+        for (wp, is_static) in self.watchpoints.iter_mut().zip(static_watchpoints) {
+            if is_static {
+                wp.addr = wp.addr.wrapping_add(delta);
+            }
+        }
+    }
+
+    /// Reads the dynamic linker's list of loaded objects and loads the symbols of the libraries
+    /// we don't know yet, resolving pending breakpoints in them. Libraries that were unloaded are
+    /// forgotten. Callers insert the breakpoints afterwards.
+    fn load_shared_libraries(&mut self) {
+        let dynamic = match self.debug_data.get_dynamic_addr() {
+            Some(dynamic) => dynamic,
+            // statically linked
+            None => return,
+        };
+        let program = match self.program() {
+            Some(program) => program,
+            None => return,
+        };
+        let (event_addr, objects) = match shared_library::find_r_debug(program, dynamic) {
+            Some(r_debug) if shared_library::is_consistent(program, r_debug) => (
+                shared_library::event_address(program, r_debug),
+                Some(shared_library::loaded_objects(program, r_debug)),
+            ),
+            // we'll be back when the dynamic linker is done
+            Some(r_debug) => (shared_library::event_address(program, r_debug), None),
+            // the dynamic linker hasn't run yet; it is done once the program's entry point is
+            // reached
+            None => (program.entry_point(), None),
+        };
+        self.solib_event_addr = event_addr;
+        let objects = match objects {
+            Some(objects) => objects,
+            None => return,
+        };
+        let unloaded: Vec<String> = self
+            .debug_data
+            .libraries()
+            .iter()
+            .filter(|lib| !objects.iter().any(|object| object.path == lib.path))
+            .map(|lib| lib.path.clone())
+            .collect();
+        for path in unloaded {
+            let debug_data = &self.debug_data;
+            for bp in self.breakpoints.iter_mut() {
+                let in_library = bp.addrs.iter().any(|addr| {
+                    debug_data.get_library_at(*addr).map_or(false, |lib| lib.path == path)
+                });
+                if in_library {
+                    bp.pending = true;
+                    bp.addrs.clear();
+                }
+            }
+            self.debug_data.remove_library(&path);
+        }
+        for object in objects {
+            if self
+                .debug_data
+                .libraries()
+                .iter()
+                .any(|lib| lib.path == object.path)
+            {
+                continue;
+            }
+            if let Err(e) = self.debug_data.add_library(&object.path, object.load_bias) {
+                println!("Err: could not load symbols from {}: {:?}", object.path, e);
+            }
+        }
+        self.resolve_pending_breakpoints();
+    }
+
+    /// Forgets the shared libraries, e.g. before the program is started again. Breakpoints in them
+    /// become pending.
+    fn clear_shared_libraries(&mut self) {
+        let debug_data = &self.debug_data;
+        for bp in self.breakpoints.iter_mut() {
+            let in_library = bp
+                .addrs
+                .iter()
+                .any(|addr| debug_data.get_library_at(*addr).is_some());
+            if in_library {
+                bp.pending = true;
+                bp.addrs.clear();
+            }
+        }
+        self.debug_data.clear_libraries();
+        self.solib_event_addr = None;
+    }
+
+    /// Gives pending breakpoints an address if their location can be found now.
+    fn resolve_pending_breakpoints(&mut self) {
+        let addrs: Vec<Vec<usize>> = self
+            .breakpoints
+            .iter()
+            .map(|bp| {
+                if bp.pending {
+                    self.resolve_location(&bp.location)
+                } else {
+                    Vec::new()
+                }
+            })
+            .collect();
+        for (bp, addrs) in self.breakpoints.iter_mut().zip(addrs) {
+            if !addrs.is_empty() {
+                bp.addrs = addrs;
+                bp.pending = false;
+            }
+        }
+    }
+
+    /// Lists the shared libraries loaded into the program, like gdb's "info sharedlibrary".
+    fn print_shared_libraries(&self) {
+        let libraries = self.debug_data.libraries();
+        if libraries.is_empty() {
+            println!("No shared libraries loaded at this time.");
+            return;
+        }
+        println!("From                To                  Syms Read   Shared Object Library");
+        let mut without_debug_info = false;
+        for lib in libraries {
+            let (from, to) = match lib.debug_data.get_text_range() {
+                Some((from, to)) => (format!("{:#018x}", from), format!("{:#018x}", to)),
+                None => (String::new(), String::new()),
+            };
+            let syms_read = if lib.debug_data.has_debug_info() {
+                "Yes"
+            } else {
+                without_debug_info = true;
+                "Yes (*)"
+            };
+            println!("{:<19} {:<19} {:<11} {}", from, to, syms_read, lib.path);
+        }
+        if without_debug_info {
+            println!("(*): Shared library is missing debugging information.");
+        }
+    }
+
+    /// Makes the 0xcc bytes in the current inferior match the breakpoint table.
+    fn insert_breakpoints(&mut self) {
+        let addrs = self.enabled_breakpoint_addrs();
+        if let Some(inferior) = self.inferior.as_mut() {
+            if let Err(e) = inferior.set_breakpoints(&addrs) {
+                println!("Err: could not insert breakpoints: {:?}", e);
+            }
+        }
+    }
+
+    /// Makes inferior `num` the current one, loading its program's symbols if needed.
+    fn switch_inferior(&mut self, num: usize) {
+        if num != self.inferior_num {
+            let pos = match self.other_inferiors.iter().position(|(n, _)| *n == num) {
+                Some(pos) => pos,
+                None => {
+                    println!("Inferior ID {} not known.", num);
+                    return;
+                }
+            };
+            let (_, inferior) = self.other_inferiors.remove(pos);
+            if let Some(current) = self.inferior.take() {
+                self.other_inferiors.push((self.inferior_num, current));
+            }
+            self.other_inferiors.sort_by_key(|(n, _)| *n);
+            let exe = inferior.exe().unwrap_or_else(|| self.target.clone());
+            self.inferior = Some(inferior);
+            self.inferior_num = num;
+            self.clear_shared_libraries();
+            match self.load_symbols(&exe) {
+                Ok(true) => self.relocate_breakpoints(),
+                Ok(false) => {}
+                Err(e) => println!(
+                    "Err: could not load debugging symbols from {}: {:?}",
+                    exe, e
+                ),
+            }
+            self.apply_load_bias();
+            self.load_shared_libraries();
+            // the breakpoint table may have changed while this process sat in the background
+            self.insert_breakpoints();
+            self.selected_frame = 0;
+            self.list_position = None;
+        }
+        match self.inferior {
+            Some(ref inferior) => {
+                println!(
+                    "[Switching to inferior {} [process {}] ({})]",
+                    num,
+                    inferior.pid(),
+                    self.target
+                );
+                if let Ok(rip) = inferior.get_rip() {
+                    self.print_stop_location(rip);
+                }
+            }
+            None => println!("[Switching to inferior {} [<null>] ({})]", num, self.target),
+        }
+    }
+
+    /// Lists the current inferior and the forked processes we keep around.
+    fn print_inferiors(&self) {
+        let mut rows = vec![(
+            self.inferior_num,
+            self.inferior.as_ref().map(|inferior| inferior.pid()),
+            self.target.clone(),
+        )];
+        for (num, inferior) in self.other_inferiors.iter() {
+            rows.push((
+                *num,
+                Some(inferior.pid()),
+                inferior.exe().unwrap_or_default(),
+            ));
+        }
+        rows.sort_by_key(|(num, _, _)| *num);
+        println!("  Num  Description       Executable");
+        for (num, pid, exe) in rows {
+            let description = match pid {
+                Some(pid) => format!("process {}", pid),
+                None => "<null>".to_string(),
+            };
+            let current = if num == self.inferior_num { "*" } else { " " };
+            println!("{} {:<4} {:<17} {}", current, num, description, exe);
+        }
+    }
+
+    /// Gives the other inferiors the events the current one collected for them while waiting.
+    fn hand_over_strays(&mut self) {
+        if let Some(inferior) = self.inferior.as_mut() {
+            for (_, other) in self.other_inferiors.iter_mut() {
+                inferior.hand_over_strays(other);
+            }
+        }
+    }
+
+    /// Kills (or detaches from, if we attached) the processes kept in the background.
+    fn kill_other_inferiors(&mut self) {
+        for (_, mut inferior) in std::mem::take(&mut self.other_inferiors) {
+            if inferior.is_attached() {
+                if let Err(e) = inferior.detach() {
+                    println!(
+                        "Err: detaching from process {} failed: {:?}",
+                        inferior.pid(),
+                        e
+                    );
+                }
+            } else {
+                inferior.kill();
+            }
+        }
+    }
+
+    /// Whether an enabled breakpoint of the user's is set at `addr`.
+    fn is_user_breakpoint(&self, addr: usize) -> bool {
+        self.breakpoints
+            .iter()
+            .any(|bp| bp.enabled && !bp.pending && bp.addrs.contains(&addr))
+    }
+
+    /// Updates hit and ignore counts of the breakpoints at `rip` and returns whether any of them
+    /// wants the inferior to stop. Stopping at an address without breakpoints (e.g. an int3
+    /// compiled into the program) always counts as a stop.
+    fn breakpoint_hit(&mut self, rip: usize) -> bool {
+        let hit: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.enabled && !bp.pending && bp.addrs.contains(&rip))
+            .map(|bp| bp.num)
+            .collect();
+        if hit.is_empty() {
+            return true;
+        }
+        let mut stop = false;
+        for num in hit {
+            let condition = self
+                .breakpoints
+                .iter()
+                .find(|bp| bp.num == num)
+                .unwrap()
+                .condition
+                .clone();
+            if let Some(condition) = condition {
+                match self.evaluate_condition(&condition) {
+                    Ok(true) => {}
+                    Ok(false) => continue,
+                    Err(err) => {
+                        println!("Error in testing condition for breakpoint {}: {}", num, err);
+                    }
+                }
+            }
+            let bp = self.breakpoints.iter_mut().find(|bp| bp.num == num).unwrap();
+            bp.hit_count += 1;
+            if bp.ignore_count > 0 {
+                bp.ignore_count -= 1;
+            } else {
+                stop = true;
+                if bp.temporary {
+                    self.delete_temporary_breakpoint(num);
+                }
+            }
+        }
+        stop
+    }
+
+    /// Deletes a "tbreak" breakpoint that just stopped the inferior.
+    fn delete_temporary_breakpoint(&mut self, num: usize) {
+        let pos = self
+            .breakpoints
+            .iter()
+            .position(|bp| bp.num == num)
+            .unwrap();
+        let bp = self.breakpoints.remove(pos);
+        println!("Temporary breakpoint {} deleted", num);
+        for addr in bp.addrs {
+            self.sync_breakpoint(addr);
+        }
+    }
+
+    /// Evaluates a breakpoint condition, which may be any expression.
+    fn evaluate_condition(&self, condition: &str) -> Result<bool, String> {
+        expression::evaluate(condition, self)?.is_true(&self.debug_data)
+    }
+
+    /// Evaluates an expression that must give an integer (or pointer).
+    fn evaluate_integer(&self, expr: &str) -> Result<i64, String> {
+        let value = expression::evaluate(expr, self)?;
+        if let Some(value) = value.as_integer(&self.debug_data) {
+            return Ok(value);
+        }
+        match value.as_float(&self.debug_data) {
+            Some(value) => Ok(value as i64),
+            None => Err(format!("Invalid cast from {}.", value.ty.name)),
+        }
+    }
+
+    /// Addresses that should hold a 0xcc, i.e. those of enabled breakpoints and the one where we
+    /// learn about shared libraries.
+    fn enabled_breakpoint_addrs(&self) -> Vec<usize> {
+        let mut addrs: Vec<usize> = self
+            .breakpoints
+            .iter()
+            .filter(|bp| bp.enabled && !bp.pending)
+            .flat_map(|bp| bp.addrs.iter().copied())
+            .chain(self.solib_event_addr)
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        addrs
+    }
+
+    /// Makes the inferior's 0xcc at `addr` match the table after a breakpoint there was deleted,
+    /// disabled or enabled. Other breakpoints may share the address, so the original byte is
+    /// only restored once no enabled breakpoint uses it.
+    fn sync_breakpoint(&mut self, addr: usize) {
+        let wanted = self.enabled_breakpoint_addrs().contains(&addr);
+        if let Some(inferior) = self.inferior.as_mut() {
+            let res = if wanted {
+                inferior.append_breakpoint(addr).map(|_| ())
+            } else {
+                inferior.remove_breakpoint(addr)
+            };
+            if let Err(e) = res {
+                println!("Err: could not update breakpoint at {:#x}: {:?}", addr, e);
+            }
+        }
+    }
+
+    /// Parses the breakpoint numbers given to delete/disable/enable. No numbers means all of
+    /// them.
+    fn parse_breakpoint_nums(&self, args: &Vec<String>) -> Option<Vec<usize>> {
+        if args.is_empty() {
+            return Some(
+                self.breakpoints
+                    .iter()
+                    .map(|bp| bp.num)
+                    .chain(self.watchpoints.iter().map(|wp| wp.num))
+                    .collect(),
+            );
+        }
+        let mut nums = Vec::new();
+        for arg in args {
+            match arg.parse::<usize>() {
+                Ok(num)
+                    if self.breakpoints.iter().any(|bp| bp.num == num)
+                        || self.watchpoints.iter().any(|wp| wp.num == num) =>
+                {
+                    nums.push(num)
+                }
+                Ok(num) => {
+                    println!("No breakpoint number {}.", num);
+                    return None;
+                }
+                Err(_) => {
+                    println!("Err: \"{}\" is not a breakpoint number", arg);
+                    return None;
+                }
+            }
+        }
+        Some(nums)
+    }
+
+    fn print_breakpoints(&self) {
+        if self.breakpoints.is_empty() && self.watchpoints.is_empty() {
+            println!("No breakpoints or watchpoints.");
+            return;
+        }
+        println!("Num     Type           Enb Address            What");
+        let mut num = 0;
+        while num < self.next_breakpoint_num {
+            if let Some(bp) = self.breakpoints.iter().find(|bp| bp.num == num) {
+                self.print_breakpoint(bp);
+            } else if let Some(wp) = self.watchpoints.iter().find(|wp| wp.num == num) {
+                println!(
+                    "{:<7} {:<14} {:<3} {:<18} {}",
+                    wp.num,
+                    "hw watchpoint",
+                    if wp.enabled { "y" } else { "n" },
+                    "",
+                    wp.expr
+                );
+                print_hit_count(wp.hit_count);
+            }
+            num += 1;
+        }
+    }
+
+    fn print_breakpoint(&self, bp: &Breakpoint) {
+        if bp.pending {
+            println!(
+                "{:<7} {:<14} {:<3} {:<18} {}",
+                bp.num,
+                "breakpoint",
+                if bp.enabled { "y" } else { "n" },
+                "<PENDING>",
+                bp.location
+            );
+            if let Some(ref condition) = bp.condition {
+                println!("\tstop only if {}", condition);
+            }
+            if bp.temporary {
+                println!("\tdeleted when hit");
+            }
+            return;
+        }
+        let enabled = if bp.enabled { "y" } else { "n" };
+        if bp.addrs.len() == 1 {
+            println!(
+                "{:<7} {:<14} {:<3} {:#018x} {}",
+                bp.num,
+                "breakpoint",
+                enabled,
+                bp.addrs[0],
+                self.describe_code_addr(bp.addrs[0])
+            );
+        } else {
+            println!(
+                "{:<7} {:<14} {:<3} <MULTIPLE>",
+                bp.num, "breakpoint", enabled
+            );
+        }
+        if let Some(ref condition) = bp.condition {
+            println!("\tstop only if {}", condition);
+        }
+        if bp.temporary {
+            println!("\tdeleted when hit");
+        }
+        print_hit_count(bp.hit_count);
+        if bp.ignore_count > 0 {
+            println!("\tWill ignore next {} crossings of breakpoint.", bp.ignore_count);
+        }
+        if bp.addrs.len() > 1 {
+            // one row per location, numbered like gdb does
+            for (i, addr) in bp.addrs.iter().enumerate() {
+                println!(
+                    "{:<7} {:<14} {:<3} {:#018x} {}",
+                    format!("{}.{}", bp.num, i + 1),
+                    "",
+                    enabled,
+                    addr,
+                    self.describe_code_addr(*addr)
+                );
+            }
+        }
+    }
+
+    /// Describes a code address for "info breakpoints": its function and line, or its symbol.
+    fn describe_code_addr(&self, addr: usize) -> String {
+        match (
+            self.debug_data.get_function_from_addr(addr),
+            self.debug_data.get_line_from_addr(addr),
+        ) {
+            (Some(func), Some(line)) => format!("in {} at {}", func, line),
+            (Some(func), None) => match self.debug_data.get_symbol_location(addr) {
+                Some(location) => format!("<{}>", location),
+                None => format!("in {}", func),
+            },
+            _ => String::new(),
+        }
+    }
+
+    fn add_watchpoint(&mut self, expr: &str) {
+        let (addr, entity_type) = match self.resolve_lvalue(expr) {
+            Ok((entity_type, addr)) => (addr, entity_type),
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let len = entity_type.size;
+        if !matches!(len, 1 | 2 | 4 | 8) || addr % len != 0 {
+            println!(
+                "Err: can only watch aligned values of 1, 2, 4 or 8 bytes ({} bytes at {:#x})",
+                len, addr
+            );
+            return;
+        }
+        let slot = match (0..NUM_WATCHPOINT_SLOTS).find(|slot| !self.watchpoints.iter().any(|wp| wp.slot == *slot)) {
+            Some(slot) => slot,
+            None => {
+                println!(
+                    "Err: all {} hardware watchpoint slots are in use, delete a watchpoint first",
+                    NUM_WATCHPOINT_SLOTS
+                );
+                return;
+            }
+        };
+        let inferior = self.inferior.as_mut().unwrap();
+        let old_value = match inferior.read_bytes(addr, len) {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                println!("Err: cannot access memory at address {:#x}", addr);
+                return;
+            }
+        };
+        if let Err(e) = inferior.set_watchpoint(slot, addr, len) {
+            println!("Err: could not set hardware watchpoint: {:?}", e);
+            return;
+        }
+        let scope = self.watchpoint_scope(expr);
+        let num = self.next_breakpoint_num;
+        self.next_breakpoint_num += 1;
+        println!("Hardware watchpoint {}: {}", num, expr);
+        self.watchpoints.push(Watchpoint {
+            num,
+            expr: expr.to_string(),
+            addr,
+            entity_type,
+            slot,
+            enabled: true,
+            hit_count: 0,
+            old_value,
+            scope,
+        });
+    }
+
+    /// Returns the selected frame if `expr` uses its local variables. Names after "." or "->"
+    /// are struct members and don't count.
+    fn watchpoint_scope(&self, expr: &str) -> Option<WatchpointScope> {
+        let (func, frame) = self.get_selected_function()?;
+        let pc = frame.lookup_addr(self.selected_frame == 0);
+        let mut words = Vec::new();
+        let mut start = None;
+        for (i, c) in expr
+            .char_indices()
+            .chain(std::iter::once((expr.len(), ' ')))
+        {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                start.get_or_insert(i);
+            } else if let Some(start) = start.take() {
+                let before = expr[..start].trim_end();
+                if !before.ends_with('.') && !before.ends_with("->") {
+                    words.push(&expr[start..i]);
+                }
+            }
+        }
+        let uses_locals = words.iter().any(|word| {
+            func.variables
+                .iter()
+                .any(|var| var.name == *word && var.in_scope(pc))
+        });
+        if !uses_locals {
+            return None;
+        }
+        let frames = self.program()?.unwind(&self.debug_data).ok()?;
+        let caller = frames.get(self.selected_frame + 1)?;
+        Some(WatchpointScope {
+            tid: self.program()?.tid(),
+            return_addr: caller.rip,
+            cfa: frame.cfa,
+        })
+    }
+
+    /// Deletes the watchpoints whose frame has returned, which is when the current thread's rsp
+    /// has come back up to the frame's CFA.
+    fn delete_out_of_scope_watchpoints(&mut self) {
+        let (tid, rsp) = match self.program() {
+            Some(program) => match program.get_regs() {
+                Ok(regs) => (program.tid(), regs.rsp as usize),
+                Err(_) => return,
+            },
+            None => return,
+        };
+        let gone: Vec<Watchpoint> = self
+            .watchpoints
+            .iter()
+            .filter(|wp| {
+                wp.scope
+                    .as_ref()
+                    .map_or(false, |scope| scope.tid == tid && rsp >= scope.cfa)
+            })
+            .cloned()
+            .collect();
+        for wp in gone {
+            print_watchpoint_out_of_scope(&wp);
+            self.watchpoints.retain(|other| other.num != wp.num);
+            if wp.enabled {
+                self.sync_watchpoint(&wp, false);
+            }
+        }
+    }
+
+    /// Arms or disarms a watchpoint's debug register in the inferior.
+    fn sync_watchpoint(&mut self, wp: &Watchpoint, armed: bool) {
+        if let Some(inferior) = self.inferior.as_mut() {
+            let res = if armed {
+                inferior.set_watchpoint(wp.slot, wp.addr, wp.entity_type.size)
+            } else {
+                inferior.clear_watchpoint(wp.slot)
+            };
+            if let Err(e) = res {
+                println!("Err: could not update watchpoint {}: {:?}", wp.num, e);
+            }
+        }
+    }
+
+    /// Writes the enabled watchpoints into a freshly started inferior.
+    fn arm_watchpoints(&mut self) {
+        for wp in self.watchpoints.clone() {
+            if wp.enabled {
+                self.sync_watchpoint(&wp, true);
+            }
+        }
+    }
+
+    /// Prints the old and new value of the watchpoint in `slot` after it fired.
+    fn report_watchpoint(&mut self, slot: usize) {
+        let wp = match self.watchpoints.iter().find(|wp| wp.slot == slot) {
+            Some(wp) => wp,
+            None => {
+                println!("Child stopped (unknown hardware watchpoint {})", slot);
+                return;
+            }
+        };
+        let new_value = self
+            .inferior
+            .as_ref()
+            .unwrap()
+            .read_bytes(wp.addr, wp.entity_type.size)
+            .unwrap_or_default();
+        println!("\nHardware watchpoint {}: {}\n", wp.num, wp.expr);
+        println!(
+            "Old value = {}",
+            self.format_value(&wp.entity_type, &wp.old_value)
+        );
+        println!(
+            "New value = {}",
+            self.format_value(&wp.entity_type, &new_value)
+        );
+        let wp = self
+            .watchpoints
+            .iter_mut()
+            .find(|wp| wp.slot == slot)
+            .unwrap();
+        wp.hit_count += 1;
+        wp.old_value = new_value;
+    }
+
+    /// Pretty-prints a value read from the inferior.
+    fn format_value(&self, entity_type: &Type, bytes: &[u8]) -> String {
+        self.debug_data
+            .format_value(entity_type, bytes, &|addr, len| self.read_memory(addr, len))
+    }
+
+    /// Reads a variable, which lives at `place` in the frame of `context`, and formats it.
+    fn read_variable(&self, context: &FrameContext, var: &Variable, place: &Place) -> String {
+        match context.read_place(place, var.entity_type.size) {
+            Ok(bytes) => self.format_value(&var.entity_type, &bytes),
+            Err(ref err) if err == OPTIMIZED_OUT => "<optimized out>".to_string(),
+            Err(err) => format!("<error: {}>", err),
+        }
+    }
+
+    /// Returns the context for evaluating variable locations in the selected frame.
+    fn selected_context(&self) -> Option<FrameContext<'_>> {
+        let frame = self.get_selected_frame()?;
+        Some(self.program()?.frame_context(&frame, self.selected_frame))
+    }
+
+    /// Returns the selected frame, freshly unwound.
+    fn get_selected_frame(&self) -> Option<Frame> {
+        let frames = self.program()?.unwind(&self.debug_data).ok()?;
+        frames.get(self.selected_frame).cloned()
+    }
+
+    /// Returns the function the selected frame is executing.
+    fn get_selected_function(&self) -> Option<(&Function, Frame)> {
+        let frame = self.get_selected_frame()?;
+        let func = self
+            .debug_data
+            .get_function_data_from_addr(frame.lookup_addr(self.selected_frame == 0))?;
+        Some((func, frame))
+    }
+
+    /// Selects frame `num` and prints it.
+    fn select_frame(&mut self, num: usize) {
+        let program = self.program().unwrap();
+        let frames = match program.unwind(&self.debug_data) {
+            Ok(frames) => frames,
+            Err(e) => {
+                println!("Err: could not unwind the stack: {:?}", e);
+                return;
+            }
+        };
+        match frames.get(num) {
+            Some(frame) => {
+                println!("{}", program.describe_frame(&self.debug_data, num, frame));
+                self.selected_frame = num;
+                self.list_position = None;
+            }
+            None => println!("No frame at level {}.", num),
+        }
+    }
+
+    /// Lists the threads with the innermost frame of each.
+    fn print_threads(&mut self) {
+        let debug_data = &self.debug_data;
+        let program = match self.inferior {
+            Some(ref mut inferior) => inferior as &mut dyn ProgramState,
+            None => self.core.as_mut().unwrap(),
+        };
+        let current = program.current_thread_num();
+        println!("  Id   Target Id         Frame");
+        for (num, tid) in program.thread_list() {
+            program.select_thread(num);
+            let frame = match program.unwind(debug_data) {
+                Ok(frames) => program.describe_frame(debug_data, 0, &frames[0]),
+                Err(_) => "(running)".to_string(),
+            };
+            println!(
+                "{} {:<4} {:<17} {}",
+                if num == current { "*" } else { " " },
+                num,
+                format!("LWP {}", tid),
+                frame.trim_start_matches("#0").trim_start()
+            );
+        }
+        program.select_thread(current);
+    }
+
+    /// Handles "thread" (show the current thread), "thread N" (switch to it) and
+    /// "thread apply all|N... bt".
+    fn thread(&mut self, args: &[String]) {
+        if args.is_empty() {
+            let program = self.program().unwrap();
+            println!(
+                "[Current thread is {} (LWP {})]",
+                program.current_thread_num(),
+                program.tid()
+            );
+            return;
+        }
+        if args[0] == "apply" {
+            self.thread_apply(&args[1..]);
+            return;
+        }
+        match args[0].parse::<usize>() {
+            Ok(num) if self.program_mut().unwrap().select_thread(num) => {
+                println!(
+                    "[Switching to thread {} (LWP {})]",
+                    num,
+                    self.program().unwrap().tid()
+                );
+                self.select_frame(0);
+            }
+            _ => println!("Invalid thread ID: {}", args[0]),
+        }
+    }
+
+    /// Prints the backtrace of each of the given threads ("all" means all of them, newest
+    /// first).
+    fn thread_apply(&mut self, args: &[String]) {
+        let (command, nums) = match args.split_last() {
+            Some((command, nums)) if !nums.is_empty() => (command, nums),
+            _ => {
+                println!("<usage>: thread apply all|<thread num>... bt");
+                return;
+            }
+        };
+        if !["bt", "back", "backtrace"].contains(&command.as_str()) {
+            println!("Err: thread apply only supports backtrace");
+            return;
+        }
+        let debug_data = &self.debug_data;
+        let program = match self.inferior {
+            Some(ref mut inferior) => inferior as &mut dyn ProgramState,
+            None => self.core.as_mut().unwrap(),
+        };
+        let nums: Vec<usize> = if nums.len() == 1 && nums[0] == "all" {
+            program
+                .thread_list()
+                .iter()
+                .rev()
+                .map(|(num, _)| *num)
+                .collect()
+        } else {
+            match nums.iter().map(|num| num.parse::<usize>()).collect() {
+                Ok(nums) => nums,
+                Err(_) => {
+                    println!("<usage>: thread apply all|<thread num>... bt");
+                    return;
+                }
+            }
+        };
+        let current = program.current_thread_num();
+        for num in nums {
+            if !program.select_thread(num) {
+                println!("Invalid thread ID: {}", num);
+                continue;
+            }
+            println!("\nThread {} (LWP {}):", num, program.tid());
+            if let Err(e) = program.backtrace(debug_data) {
+                println!("Err: could not unwind the stack: {:?}", e);
+            }
+        }
+        program.select_thread(current);
+    }
+
+    /// Moves the selected frame `count` frames towards the caller (up) or the callee (down).
+    fn move_frame(&mut self, up: bool, count: usize) {
+        let num_frames = match self.program().unwrap().unwind(&self.debug_data) {
+            Ok(frames) => frames.len(),
+            Err(e) => {
+                println!("Err: could not unwind the stack: {:?}", e);
+                return;
+            }
+        };
+        if up {
+            if self.selected_frame + 1 >= num_frames {
+                println!("Initial frame selected; you cannot go up.");
+            } else {
+                self.select_frame((self.selected_frame + count).min(num_frames - 1));
+            }
+        } else if self.selected_frame == 0 {
+            println!("Bottom (innermost) frame selected; you cannot go down.");
+        } else {
+            self.select_frame(self.selected_frame.saturating_sub(count));
+        }
+    }
+
+    /// Returns the source line of the selected frame, or of main if nothing is running.
+    fn get_current_line(&self) -> Option<Line> {
+        match self.program() {
+            Some(_) => {
+                let frame = self.get_selected_frame()?;
+                self.debug_data
+                    .get_line_from_addr(frame.lookup_addr(self.selected_frame == 0))
+            }
+            None => {
+                let main = self.debug_data.get_addr_for_function(None, "main")?;
+                self.debug_data.get_line_from_addr(main)
+            }
+        }
+    }
+
+    /// Implements "list", "list <line>" and "list <function>".
+    fn list(&mut self, arg: Option<String>) {
+        // the line under the arrow, when it is in the file being listed
+        let current = self.get_current_line().filter(|_| self.program().is_some());
+        let marker = |file: &str| {
+            current
+                .as_ref()
+                .filter(|line| line.file == file)
+                .map(|line| line.number)
+        };
+        let position = match arg {
+            None => match self.list_position.clone() {
+                Some(pos) => source::print_lines(&pos.file, pos.next_line, marker(&pos.file)),
+                None => match self.get_current_line() {
+                    Some(line) => source::print_around(&line.file, line.number, marker(&line.file)),
+                    None => {
+                        println!("No line number information available.");
+                        None
+                    }
+                },
+            },
+            Some(arg) => {
+                if let Ok(number) = arg.parse::<usize>() {
+                    // a line in the file we were looking at
+                    let file = match self.list_position {
+                        Some(ref pos) => Some(pos.file.clone()),
+                        None => self.get_current_line().map(|line| line.file),
+                    };
+                    match file {
+                        Some(file) => source::print_around(&file, number, marker(&file)),
+                        None => {
+                            println!("No line number information available.");
+                            None
+                        }
+                    }
+                } else {
+                    match self
+                        .debug_data
+                        .get_addr_for_function(None, &arg)
+                        .and_then(|addr| self.debug_data.get_line_from_addr(addr))
+                    {
+                        Some(line) => source::print_around(&line.file, line.number, marker(&line.file)),
+                        None => {
+                            println!("Function \"{}\" not defined.", arg);
+                            None
+                        }
+                    }
+                }
+            }
+        };
+        // keep the old position if nothing could be listed
+        if position.is_some() {
+            self.list_position = position;
+        }
+    }
+
+    /// Reads memory from the inferior (without our breakpoints) or the core dump, or from the
+    /// executable's sections when nothing is running. Returns as many of the `len` bytes as could
+    /// be read.
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        match (&self.inferior, &self.core) {
+            (Some(inferior), _) => inferior.read_memory(addr, len).ok(),
+            // cores leave out the code, which is still in the executable
+            (None, Some(core)) => core
+                .read_memory(addr, len)
+                .ok()
+                .or_else(|| self.debug_data.get_file_bytes(addr, len)),
+            (None, None) => self.debug_data.get_file_bytes(addr, len),
+        }
+    }
+
+    /// Evaluates an address argument: an expression giving a pointer or integer. Arrays and
+    /// functions give their address.
+    fn parse_location(&self, arg: &str) -> Result<usize, String> {
+        let value = expression::evaluate(arg, self)?;
+        if let Some(addr) = value.as_integer(&self.debug_data) {
+            return Ok(addr as usize);
+        }
+        match value.address {
+            Some(addr) if !value.ty.is_float() => Ok(addr),
+            _ => Err(format!("Invalid cast from {}.", value.ty.name)),
+        }
+    }
+
+    /// Implements "disassemble [function|*addr]", which lists the whole function containing the
+    /// address (the selected frame's pc by default).
+    fn disassemble(&self, arg: Option<String>) {
+        let current = self.get_selected_frame().map(|frame| frame.rip);
+        let addr = match arg {
+            // "*addr" as in "break *addr"
+            Some(ref arg) => match self.parse_location(arg.trim_start_matches('*')) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => match current {
+                Some(rip) => rip,
+                None => {
+                    println!("No frame selected.");
+                    return;
+                }
+            },
+        };
+        let func = match self.debug_data.get_function_data_from_addr(addr) {
+            Some(func) => func,
+            None => {
+                println!("No function contains specified address.");
+                return;
+            }
+        };
+        match self.read_memory(func.address, func.text_length) {
+            Some(code) => {
+                println!("Dump of assembler code for function {}:", func.name);
+                disassemble::print_instructions(
+                    &self.debug_data,
+                    &code,
+                    func.address,
+                    None,
+                    current,
+                );
+                println!("End of assembler dump.");
+            }
+            None => println!("Cannot access memory at address {:#x}", func.address),
+        }
+    }
+
+    /// Implements "x/FMT addr". Without an address, continues where the last x left off.
+    fn examine(&mut self, spec: &str, arg: &str) {
+        let format = match ExamineFormat::parse(spec) {
+            Ok(format) => format,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let addr = if arg.trim().is_empty() {
+            match self.next_examine_addr {
+                Some(addr) => addr,
+                None => {
+                    println!("Argument required (starting display address).");
+                    return;
+                }
+            }
+        } else {
+            match self.parse_location(arg) {
+                Ok(addr) => addr,
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        };
+        let next_addr = match format.format {
+            'i' => self.examine_instructions(&format, addr),
+            's' => self.examine_strings(&format, addr),
+            _ => self.examine_units(&format, addr),
+        };
+        self.next_examine_addr = Some(next_addr);
+    }
+
+    /// Prints `format.count` units of memory at `addr`, returning the address after them.
+    fn examine_units(&self, format: &ExamineFormat, addr: usize) -> usize {
+        let mut addr = addr;
+        let mut remaining = format.count;
+        while remaining > 0 {
+            let chunk = ExamineFormat {
+                count: remaining.min(examine::CHUNK_UNITS),
+                ..*format
+            };
+            let len = chunk.count * chunk.size;
+            let bytes = self.read_memory(addr, len).unwrap_or_default();
+            let printed = examine::print_units(&self.debug_data, &chunk, &bytes, addr);
+            if printed < len {
+                println!(
+                    "Cannot access memory at address {:#x}",
+                    addr.wrapping_add(printed)
+                );
+                return addr.wrapping_add(printed);
+            }
+            addr = addr.wrapping_add(printed);
+            remaining -= chunk.count;
+        }
+        addr
+    }
+
+    /// Disassembles `format.count` instructions at `addr`, returning the address after them.
+    fn examine_instructions(&self, format: &ExamineFormat, addr: usize) -> usize {
+        let current = self.get_selected_frame().map(|frame| frame.rip);
+        let mut addr = addr;
+        let mut remaining = format.count;
+        while remaining > 0 {
+            let count = remaining.min(examine::CHUNK_UNITS);
+            let len = count * MAX_INSTRUCTION_LEN;
+            let code = match self.read_memory(addr, len) {
+                Some(code) => code,
+                None => {
+                    println!("Cannot access memory at address {:#x}", addr);
+                    return addr;
+                }
+            };
+            addr = disassemble::print_instructions(
+                &self.debug_data,
+                &code,
+                addr,
+                Some(count),
+                current,
+            );
+            if code.len() < len {
+                // the rest isn't readable
+                return addr;
+            }
+            remaining -= count;
+        }
+        addr
+    }
+
+    /// Prints `format.count` consecutive C strings at `addr`, returning the address after them.
+    fn examine_strings(&self, format: &ExamineFormat, addr: usize) -> usize {
+        let mut addr = addr;
+        for _ in 0..format.count {
+            let bytes = self
+                .read_memory(addr, examine::MAX_STRING_LEN + 1)
+                .unwrap_or_default();
+            match examine::print_string(&self.debug_data, &bytes, addr) {
+                Some(consumed) => addr += consumed,
+                None => {
+                    println!("Cannot access memory at address {:#x}", addr + bytes.len());
+                    break;
+                }
+            }
+        }
+        addr
+    }
+
+    fn read_register(&self, name: &str) -> Result<u64, String> {
+        let program = self
+            .program()
+            .ok_or("The program has no registers now.".to_string())?;
+        match program.get_register(name) {
+            Ok(Some(value)) => Ok(value),
+            Ok(None) => Err(format!("Invalid register `{}'", name)),
+            Err(err) => Err(format!("Couldn't get registers: {}", err)),
+        }
+    }
+
+    /// Formats a register value the way "info registers" shows it in its last column.
+    fn format_register(&self, name: &str, value: u64) -> String {
+        if name == "eflags" {
+            registers::format_eflags(value)
+        } else if name == "rip" || name == "pc" {
+            format!(
+                "{:#x} {}",
+                value,
+                disassemble::symbolize(&self.debug_data, value as usize)
+            )
+        } else if registers::is_pointer_register(name) {
+            format!("{:#x}", value)
+        } else {
+            format!("{}", value as i64)
+        }
+    }
+
+    /// Implements "info registers [name]".
+    fn print_registers(&self, name: Option<String>) {
+        let names = match name {
+            Some(ref name) => vec![name.trim_start_matches('$')],
+            None => registers::REGISTER_NAMES.to_vec(),
+        };
+        for name in names {
+            match self.read_register(name) {
+                Ok(value) => println!(
+                    "{:<15}{:<19}{}",
+                    name,
+                    format!("{:#x}", value),
+                    self.format_register(name, value)
+                ),
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Implements "set $reg = value", "set var name = value" and "set *(type *)addr = value".
+    fn set(&mut self, expr: &str) {
+        let expr = expr.trim();
+        if self.set_option(expr) {
+            return;
+        }
+        let expr = if expr.starts_with("var ") || expr.starts_with("variable ") {
+            expr.splitn(2, ' ').nth(1).unwrap_or("")
+        } else {
+            expr
+        };
+        let (lhs, rhs) = match split_assignment(expr) {
+            Some((lhs, rhs)) if !lhs.is_empty() && !rhs.is_empty() => (lhs, rhs),
+            _ => {
+                println!("<usage>: set [var] <variable|$register|*(type *)addr> = <value>");
+                return;
+            }
+        };
+        if self.inferior.is_none() {
+            println!("Err: no process is running yet");
+            return;
+        }
+        let res = if lhs.starts_with('$') {
+            self.set_register(&lhs[1..], rhs)
+        } else {
+            self.set_memory(lhs, rhs)
+        };
+        if let Err(err) = res {
+            println!("{}", err);
+        }
+    }
+
+    /// Handles "set follow-fork-mode" and "set detach-on-fork". Returns false if `expr` is
+    /// not about one of them.
+    fn set_option(&mut self, expr: &str) -> bool {
+        let words: Vec<&str> = expr.split_whitespace().collect();
+        match words.as_slice() {
+            ["follow-fork-mode", "parent"] => self.follow_fork_mode = FollowForkMode::Parent,
+            ["follow-fork-mode", "child"] => self.follow_fork_mode = FollowForkMode::Child,
+            ["follow-fork-mode", ..] => {
+                println!("Requires an argument. Valid arguments are child, parent.");
+                return true;
+            }
+            ["detach-on-fork", "on"] => self.detach_on_fork = true,
+            ["detach-on-fork", "off"] => self.detach_on_fork = false,
+            ["disable-randomization", "on"] => self.disable_randomization = true,
+            ["disable-randomization", "off"] => self.disable_randomization = false,
+            ["detach-on-fork", ..] | ["disable-randomization", ..] => {
+                println!("\"on\" or \"off\" expected.");
+                return true;
+            }
+            _ => return false,
+        }
+        self.configure_inferiors();
+        true
+    }
+
+    /// Hands the fork options and the signal table to all inferiors.
+    fn configure_inferiors(&mut self) {
+        let (mode, detach) = (self.follow_fork_mode, self.detach_on_fork);
+        for inferior in self.inferior.iter_mut().chain(
+            self.other_inferiors
+                .iter_mut()
+                .map(|(_, inferior)| inferior),
+        ) {
+            inferior.set_fork_options(mode, detach);
+            inferior.set_signal_table(&self.signal_table);
+        }
+    }
+
+    /// Handles "handle SIGNAL [keywords...]", which changes what happens when the inferior
+    /// receives SIGNAL (or "all" signals).
+    fn handle_signal(&mut self, args: &[String]) {
+        let (name, keywords) = match args.split_first() {
+            Some(split) => split,
+            None => {
+                println!("Argument required (signal and action to apply).");
+                return;
+            }
+        };
+        let signals: Vec<Signal> = if name == "all" {
+            // all doesn't include the signals the debugger uses
+            Signal::iterator()
+                .filter(|signal| *signal != Signal::SIGTRAP && *signal != Signal::SIGINT)
+                .collect()
+        } else {
+            match signals::parse_signal(name) {
+                Ok(Signal::SIGTRAP) if !keywords.is_empty() => {
+                    println!("SIGTRAP is used by the debugger.");
+                    return;
+                }
+                Ok(signal) => vec![signal],
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            }
+        };
+        // check every keyword first so a typo doesn't leave the table half changed
+        const KEYWORDS: [&str; 8] = [
+            "stop", "nostop", "print", "noprint", "pass", "noignore", "nopass", "ignore",
+        ];
+        if let Some(keyword) = keywords.iter().find(|k| !KEYWORDS.contains(&k.as_str())) {
+            println!("Unrecognized or ambiguous flag word: \"{}\".", keyword);
+            return;
+        }
+        for keyword in keywords {
+            for signal in signals.iter() {
+                let policy = self.signal_table.get_mut(*signal);
+                match keyword.as_str() {
+                    "stop" => {
+                        policy.stop = true;
+                        policy.print = true;
+                    }
+                    "nostop" => policy.stop = false,
+                    "print" => policy.print = true,
+                    "noprint" => {
+                        policy.print = false;
+                        policy.stop = false;
+                    }
+                    "pass" | "noignore" => policy.pass = true,
+                    _ => policy.pass = false,
+                }
+            }
+        }
+        self.configure_inferiors();
+        println!("{}", signals::TABLE_HEADER);
+        for signal in signals {
+            println!("{}", self.signal_table.format_row(signal));
+        }
+    }
+
+    /// Prints the policy of `name`, or of all signals.
+    fn print_signals(&self, name: Option<String>) {
+        let signals: Vec<Signal> = match name {
+            Some(name) => match signals::parse_signal(&name) {
+                Ok(signal) => vec![signal],
+                Err(err) => {
+                    println!("{}", err);
+                    return;
+                }
+            },
+            None => Signal::iterator().collect(),
+        };
+        println!("{}", signals::TABLE_HEADER);
+        println!();
+        for signal in signals {
+            println!("{}", self.signal_table.format_row(signal));
+        }
+    }
+
+    fn set_register(&mut self, name: &str, rhs: &str) -> Result<(), String> {
+        let value = self.evaluate_integer(rhs)?;
+        match self.inferior.as_mut().unwrap().set_register(name, value as u64) {
+            Ok(true) => {
+                // a new rip or rsp can change the whole stack
+                self.selected_frame = 0;
+                self.list_position = None;
+                Ok(())
+            }
+            Ok(false) => Err(format!("Invalid register `{}'", name)),
+            Err(err) => Err(format!("Couldn't write registers: {}", err)),
+        }
+    }
+
+    fn set_memory(&mut self, lhs: &str, rhs: &str) -> Result<(), String> {
+        let (entity_type, addr) = self.resolve_lvalue(lhs)?;
+        let entity_type = match self.debug_data.strip_aliases(&entity_type) {
+            Some(entity_type) if entity_type.is_scalar() => entity_type.clone(),
+            _ => {
+                return Err(format!(
+                    "Cannot assign to a value of type {}",
+                    entity_type.name
+                ))
+            }
+        };
+        let bytes = if entity_type.is_float() {
+            let value = expression::evaluate(rhs, self)?;
+            let value = value
+                .as_float(&self.debug_data)
+                .ok_or(format!("Invalid cast from {}.", value.ty.name))?;
+            entity_type.encode_float(value).ok_or(format!(
+                "Cannot assign to a value of type {}",
+                entity_type.name
+            ))?
+        } else {
+            entity_type.encode_integer(self.evaluate_integer(rhs)?)
+        };
+        self.inferior
+            .as_mut()
+            .unwrap()
+            .write_bytes(addr, &bytes)
+            .or(Err(format!("Cannot access memory at address {:#x}", addr)))
+    }
+
+    /// Finds the type and address of the target of an assignment (or watchpoint), which must be
+    /// in memory.
+    fn resolve_lvalue(&self, lhs: &str) -> Result<(Type, usize), String> {
+        let value = expression::evaluate(lhs, self)?;
+        match value.address {
+            Some(addr) => Ok((value.ty, addr)),
+            // explain why a variable isn't in memory
+            None if self.lookup_variable(lhs.trim()).is_some() => self.variable_address(lhs.trim()),
+            None => Err("Left operand of assignment is not an lvalue.".to_string()),
+        }
+    }
+
+    /// Finds the variable called `name` in the selected frame, falling back to globals. Returns
+    /// it along with where its value currently lives.
+    fn lookup_variable(&self, name: &str) -> Option<(&Variable, Place)> {
+        let context = self.selected_context()?;
+        // locals shadow globals, and variables in inner blocks shadow the ones outside them
+        let local = self.get_selected_function().and_then(|(func, _)| {
+            func.variables
+                .iter()
+                .filter(|var| var.name == name && var.in_scope(context.pc()))
+                .last()
+        });
+        let var = match local {
+            Some(local) => local,
+            None => self.debug_data.get_global_variable(name)?,
+        };
+        Some((var, var.location.evaluate(&context)))
+    }
+
+    /// Returns the type and address of the variable called `name`, which must be in memory.
+    fn variable_address(&self, name: &str) -> Result<(Type, usize), String> {
+        let (var, place) = self
+            .lookup_variable(name)
+            .ok_or(format!("No symbol \"{}\" in current context.", name))?;
+        match place {
+            Place::Memory(addr) => Ok((var.entity_type.clone(), addr)),
+            Place::Register(register) => {
+                let register = match registers::dwarf_xmm_register(register) {
+                    Some(xmm) => format!("xmm{}", xmm),
+                    None => registers::dwarf_register_name(register)
+                        .unwrap_or("?")
+                        .to_string(),
+                };
+                Err(format!(
+                    "Address requested for identifier \"{}\" which is in register ${}",
+                    name, register
+                ))
+            }
+            Place::OptimizedOut => Err(OPTIMIZED_OUT.to_string()),
+            _ => Err(format!(
+                "Can't take address of \"{}\" which isn't an lvalue.",
+                name
+            )),
+        }
+    }
+
+    fn print_expression(&self, expr: &str) {
+        let is_identifier =
+            |name: &str| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if expr.starts_with('$') && is_identifier(&expr[1..]) {
+            match self.read_register(&expr[1..]) {
+                Ok(value) => {
+                    println!("{} = {}", expr, self.format_register(&expr[1..], value))
+                }
+                Err(err) => println!("{}", err),
+            }
+            return;
+        }
+        // plain variables may be (partly) optimized out, which isn't an error
+        if let Some((var, place)) = self.lookup_variable(expr) {
+            match self.selected_context() {
+                Some(context) => {
+                    println!("{} = {}", expr, self.read_variable(&context, var, &place))
+                }
+                None => println!("Err: could not unwind the stack"),
+            }
+            return;
+        }
+        match expression::evaluate(expr, self) {
+            Ok(value) => println!("{} = {}", expr, self.format_value(&value.ty, &value.bytes)),
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    fn print_locals(&self) {
+        let (func, context) = match (self.get_selected_function(), self.selected_context()) {
+            (Some((func, _)), Some(context)) => (func, context),
+            _ => {
+                println!("No symbol table info available.");
+                return;
+            }
+        };
+        let locals: Vec<&Variable> = func
+            .variables
+            .iter()
+            .filter(|var| var.in_scope(context.pc()))
+            .collect();
+        if locals.is_empty() {
+            println!("No locals.");
+            return;
+        }
+        for var in locals {
+            let place = var.location.evaluate(&context);
+            println!("{} = {}", var.name, self.read_variable(&context, var, &place));
+        }
+    }
+
+    /// This function prompts the user to enter a command, and continues re-prompting until the user
+    /// enters a valid command. It uses DebuggerCommand::from_tokens to do the command parsing.
+    ///
+    /// You don't need to read, understand, or modify this function.
+    fn get_next_command(&mut self) -> DebuggerCommand {
+        loop {
+            // Print prompt and get next line of user input
+            match self.readline.readline("(deet) ") {
+                Err(ReadlineError::Interrupted) => {
+                    // User pressed ctrl+c. We're going to ignore it
+                    println!("Type \"quit\" to exit");
+                }
+                Err(ReadlineError::Eof) => {
+                    // User pressed ctrl+d, which is the equivalent of "quit" for our purposes
+                    return DebuggerCommand::Quit;
+                }
+                Err(err) => {
+                    panic!("Unexpected I/O error: {:?}", err);
+                }
+                Ok(line) => {
+                    if line.trim().len() == 0 {
+                        continue;
+                    }
+                    self.readline.add_history_entry(line.as_str());
+                    if let Err(err) = self.readline.save_history(&self.history_path) {
+                        println!(
+                            "Warning: failed to save history file at {}: {}",
+                            self.history_path, err
+                        );
+                    }
+                    let tokens = debugger_command::tokenize(&line);
+                    if let Some(cmd) = DebuggerCommand::from_tokens(&tokens) {
+                        return cmd;
+                    } else {
+                        println!("Unrecognized command.");
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Splits "lhs = rhs" at the assignment, which is the first "=" that isn't part of a
+/// comparison operator.
+fn split_assignment(expr: &str) -> Option<(&str, &str)> {
+    let bytes = expr.as_bytes();
+    let pos = (0..bytes.len()).find(|&i| {
+        bytes[i] == b'='
+            && bytes.get(i + 1) != Some(&b'=')
+            && (i == 0 || !b"=!<>".contains(&bytes[i - 1]))
+    })?;
+    Some((expr[..pos].trim(), expr[pos + 1..].trim()))
+}
+
+impl Environment for Debugger {
+    fn debug_data(&self) -> &DwarfData {
+        &self.debug_data
+    }
+
+    fn variable(&self, name: &str) -> Option<Result<Value, String>> {
+        let (var, place) = self.lookup_variable(name)?;
+        let bytes = self
+            .selected_context()?
+            .read_place(&place, var.entity_type.size);
+        Some(bytes.map(|bytes| Value {
+            ty: var.entity_type.clone(),
+            bytes,
+            address: match place {
+                Place::Memory(addr) => Some(addr),
+                _ => None,
+            },
+        }))
+    }
+
+    fn register(&self, name: &str) -> Result<u64, String> {
+        self.read_register(name)
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        Debugger::read_memory(self, addr, len)
+    }
+}
+
+fn print_watchpoint_out_of_scope(wp: &Watchpoint) {
+    println!(
+        "Watchpoint {} deleted because the program has left the block in which its expression \
+         is valid.",
+        wp.num
+    );
+}
+
+fn print_hit_count(hit_count: usize) {
+    if hit_count > 0 {
+        println!(
+            "\tbreakpoint already hit {} time{}",
+            hit_count,
+            if hit_count == 1 { "" } else { "s" }
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("x = 1"), Some(("x", "1")));
+        assert_eq!(split_assignment("p->x=3"), Some(("p->x", "3")));
+        assert_eq!(split_assignment("a[i] = b == c"), Some(("a[i]", "b == c")));
+        assert_eq!(split_assignment("x == 1"), None);
+        assert_eq!(split_assignment("x != 1"), None);
+        assert_eq!(split_assignment("x <= 1"), None);
+        assert_eq!(split_assignment("x >= 1"), None);
+        assert_eq!(split_assignment("x + 1"), None);
     }
 }
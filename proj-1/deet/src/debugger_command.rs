@@ -2,16 +2,87 @@ pub enum DebuggerCommand {
     Quit,
     Cont,
     Back,
+    Up(Option<String>),
+    Down(Option<String>),
+    Frame(Option<String>),
+    StepInstruction,
+    Next,
+    Step,
+    Finish,
+    Until(String),
+    Advance(String),
+    Print(String),
+    List(Option<String>),
+    Disassemble(Option<String>),
+    Examine(String, String),
+    InfoLocals,
+    InfoGlobals,
+    InfoBreakpoints,
+    InfoInferiors,
+    InfoThreads,
+    InfoSignals(Option<String>),
+    InfoSharedLibrary,
+    InfoRegisters(Option<String>),
+    Set(String),
+    Delete(Vec<String>),
+    Disable(Vec<String>),
+    Enable(Vec<String>),
+    Ignore(Vec<String>),
+    Watch(String),
     BreakPoint(Vec<String>),
+    TempBreakPoint(Vec<String>),
     Run(Vec<String>),
+    Attach(String),
+    Detach,
+    Inferior(String),
+    Thread(Vec<String>),
+    Handle(Vec<String>),
 }
 
 impl DebuggerCommand {
     pub fn from_tokens(tokens: &Vec<&str>) -> Option<DebuggerCommand> {
+        // x/FMT takes its format glued to the command
+        if tokens[0] == "x" || tokens[0].starts_with("x/") {
+            let format = tokens[0].trim_start_matches('x').trim_start_matches('/');
+            return Some(DebuggerCommand::Examine(format.to_string(), tokens[1..].join(" ")));
+        }
         match tokens[0] {
             "q" | "quit" => Some(DebuggerCommand::Quit),
             "c" | "cont" | "continue" => Some(DebuggerCommand::Cont),
             "bt" | "back" | "backtrace" => Some(DebuggerCommand::Back),
+            "up" => Some(DebuggerCommand::Up(tokens.get(1).map(|s| s.to_string()))),
+            "down" => Some(DebuggerCommand::Down(tokens.get(1).map(|s| s.to_string()))),
+            "f" | "frame" => Some(DebuggerCommand::Frame(tokens.get(1).map(|s| s.to_string()))),
+            "si" | "stepi" => Some(DebuggerCommand::StepInstruction),
+            "n" | "next" => Some(DebuggerCommand::Next),
+            "s" | "step" => Some(DebuggerCommand::Step),
+            "fin" | "finish" => Some(DebuggerCommand::Finish),
+            "u" | "until" => Some(DebuggerCommand::Until(tokens[1..].join(" "))),
+            "advance" => Some(DebuggerCommand::Advance(tokens[1..].join(" "))),
+            "l" | "list" => Some(DebuggerCommand::List(tokens.get(1).map(|s| s.to_string()))),
+            "disas" | "disassemble" => {
+                Some(DebuggerCommand::Disassemble(tokens.get(1).map(|s| s.to_string())))
+            }
+            "p" | "print" => Some(DebuggerCommand::Print(tokens[1..].join(" "))),
+            "info" => match tokens.get(1) {
+                Some(&"locals") => Some(DebuggerCommand::InfoLocals),
+                Some(&"globals") => Some(DebuggerCommand::InfoGlobals),
+                Some(&"b") | Some(&"break") | Some(&"breakpoints") => {
+                    Some(DebuggerCommand::InfoBreakpoints)
+                }
+                Some(&"inferiors") => Some(DebuggerCommand::InfoInferiors),
+                Some(&"threads") => Some(DebuggerCommand::InfoThreads),
+                Some(&"signals") | Some(&"handle") => Some(DebuggerCommand::InfoSignals(
+                    tokens.get(2).map(|s| s.to_string()),
+                )),
+                Some(&"sharedlibrary") | Some(&"shared") | Some(&"dll") => {
+                    Some(DebuggerCommand::InfoSharedLibrary)
+                }
+                Some(&"r") | Some(&"reg") | Some(&"registers") => Some(
+                    DebuggerCommand::InfoRegisters(tokens.get(2).map(|s| s.to_string())),
+                ),
+                _ => None,
+            },
             "b" | "break" => {
                 // do more parsing
                 let args = tokens[1..].to_vec();
@@ -19,14 +90,123 @@ impl DebuggerCommand {
                     args.iter().map(|s| s.to_string()).collect(),
                 ))
             }
-            "r" | "run" => {
-                let args = tokens[1..].to_vec();
-                Some(DebuggerCommand::Run(
-                    args.iter().map(|s| s.to_string()).collect(),
-                ))
-            }
+            "tb" | "tbreak" => Some(DebuggerCommand::TempBreakPoint(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "d" | "delete" => Some(DebuggerCommand::Delete(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "disable" => Some(DebuggerCommand::Disable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "enable" => Some(DebuggerCommand::Enable(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "set" => Some(DebuggerCommand::Set(tokens[1..].join(" "))),
+            "watch" => Some(DebuggerCommand::Watch(tokens[1..].join(" "))),
+            "ignore" => Some(DebuggerCommand::Ignore(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "attach" => Some(DebuggerCommand::Attach(tokens[1..].join(" "))),
+            "detach" => Some(DebuggerCommand::Detach),
+            "inferior" => Some(DebuggerCommand::Inferior(tokens[1..].join(" "))),
+            "handle" => Some(DebuggerCommand::Handle(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "thread" => Some(DebuggerCommand::Thread(
+                tokens[1..].iter().map(|s| s.to_string()).collect(),
+            )),
+            "r" | "run" => Some(DebuggerCommand::Run(
+                tokens[1..].iter().map(|s| unquote(s)).collect(),
+            )),
             // Default case:
             _ => None,
         }
     }
 }
+
+/// Splits a command line into words at whitespace, except inside quotes, so that arguments such
+/// as `c == ' '` survive intact.
+pub fn tokenize(line: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if quote.is_some() {
+            match c {
+                '\\' => escaped = true,
+                _ if Some(c) == quote => quote = None,
+                _ => {}
+            }
+        } else if c.is_whitespace() {
+            if let Some(start) = start.take() {
+                tokens.push(&line[start..i]);
+            }
+            continue;
+        } else if c == '\'' || c == '"' {
+            quote = Some(c);
+        }
+        start.get_or_insert(i);
+    }
+    if let Some(start) = start {
+        tokens.push(&line[start..]);
+    }
+    tokens
+}
+
+/// Turns a token into the word a shell would pass on: quotes are removed, and a backslash inside
+/// quotes escapes the next character (the same rules `tokenize` uses to find the token's end).
+fn unquote(token: &str) -> String {
+    let mut word = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in token.chars() {
+        if escaped {
+            word.push(c);
+            escaped = false;
+        } else if quote.is_some() {
+            match c {
+                '\\' => escaped = true,
+                _ if Some(c) == quote => quote = None,
+                _ => word.push(c),
+            }
+        } else if c == '\'' || c == '"' {
+            quote = Some(c);
+        } else {
+            word.push(c);
+        }
+    }
+    word
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(tokenize("  break   main "), vec!["break", "main"]);
+        assert_eq!(
+            tokenize("run \"hello world\" 'a b' c"),
+            vec!["run", "\"hello world\"", "'a b'", "c"]
+        );
+        assert_eq!(tokenize(r#"print "a \" b""#), vec!["print", r#""a \" b""#]);
+        assert_eq!(tokenize("print 'x'+1 y"), vec!["print", "'x'+1", "y"]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn test_run_args() {
+        let line = r#"run "hello world" 'a b' c "x\"y" pre"fix"ed 'it\'s' """#;
+        match DebuggerCommand::from_tokens(&tokenize(line)) {
+            Some(DebuggerCommand::Run(args)) => assert_eq!(
+                args,
+                vec!["hello world", "a b", "c", "x\"y", "prefixed", "it's", ""]
+            ),
+            _ => panic!("not parsed as run"),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::dwarf_data::DwarfData;
+use iced_x86::{Decoder, DecoderOptions, Formatter, GasFormatter, Instruction};
+
+/// Longest possible x86-64 instruction
+pub const MAX_INSTRUCTION_LEN: usize = 15;
+
+/// Formats `addr` as <symbol+offset>, or an empty string if it isn't in a known function or
+/// global variable.
+pub fn symbolize(debug_data: &DwarfData, addr: usize) -> String {
+    if let Some(func) = debug_data.get_function_data_from_addr(addr) {
+        return format!("<{}+{}>", func.name, addr - func.address);
+    }
+    if let Some((sym, offset)) = debug_data.get_symbol_from_addr(addr) {
+        return format!("<{}+{}>", sym.name, offset);
+    }
+    match debug_data.get_global_variable_from_addr(addr) {
+        Some(var) => match addr - var.location.static_address().unwrap_or(addr) {
+            0 => format!("<{}>", var.name),
+            offset => format!("<{}+{}>", var.name, offset),
+        },
+        None => String::new(),
+    }
+}
+
+/// Disassembles `code`, which was read from `addr`, printing at most `max_instructions`
+/// instructions (all of them if None) in AT&T syntax. `current` is marked with an arrow. Returns
+/// the address following the last instruction printed.
+pub fn print_instructions(
+    debug_data: &DwarfData,
+    code: &[u8],
+    addr: usize,
+    max_instructions: Option<usize>,
+    current: Option<usize>,
+) -> usize {
+    let mut decoder = Decoder::new(64, code, DecoderOptions::NONE);
+    decoder.set_ip(addr as u64);
+    let mut formatter = GasFormatter::new();
+    formatter.options_mut().set_uppercase_hex(false);
+    let mut instruction = Instruction::default();
+    let mut output = String::new();
+    let mut count = 0;
+    let mut next_addr = addr;
+    while decoder.can_decode() && max_instructions.map_or(true, |max| count < max) {
+        decoder.decode_out(&mut instruction);
+        if instruction.is_invalid() && decoder.position() == code.len() {
+            // we probably cut the last instruction short
+            break;
+        }
+        output.clear();
+        formatter.format(&instruction, &mut output);
+        let ip = instruction.ip() as usize;
+        // name the target of calls and jumps
+        let target = instruction.near_branch_target() as usize;
+        if target != 0 {
+            let symbol = symbolize(debug_data, target);
+            if !symbol.is_empty() {
+                output.push(' ');
+                output.push_str(&symbol);
+            }
+        }
+        println!(
+            "{} {:#018x} {}:\t{}",
+            if Some(ip) == current { "=>" } else { "  " },
+            ip,
+            symbolize(debug_data, ip),
+            output
+        );
+        count += 1;
+        next_addr = instruction.next_ip() as usize;
+    }
+    next_addr
+}
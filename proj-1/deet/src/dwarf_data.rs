@@ -1,9 +1,17 @@
+use crate::examine::escape_char;
 use crate::gimli_wrapper;
+pub use crate::gimli_wrapper::{DwarfExpression, FrameRule};
 use addr2line::Context;
 use object::Object;
+use std::collections::HashMap;
 use std::convert::TryInto;
+use std::mem::size_of;
+use std::path::Path;
 use std::{fmt, fs};
 
+/// Don't follow type references deeper than this (e.g. in malformed, cyclic DWARF)
+pub const MAX_TYPE_DEPTH: usize = 32;
+
 #[derive(Debug)]
 pub enum Error {
     ErrorOpeningFile,
@@ -12,7 +20,42 @@ pub enum Error {
 
 pub struct DwarfData {
     files: Vec<File>,
+    /// Every type in the program, by the .debug_info offset of its DIE
+    types: HashMap<usize, Type>,
+    cfi: gimli_wrapper::CallFrameInfo,
+    /// Contents of the code and initialized data sections, for reading without a running process
+    loaded_sections: Vec<(usize, Vec<u8>)>,
     addr2line: Context<addr2line::gimli::EndianRcSlice<addr2line::gimli::RunTimeEndian>>,
+    /// Link-time address of the entry point
+    entry: usize,
+    /// Difference between the addresses the program was loaded at and its link-time addresses.
+    /// The addresses we hand out include it; the CFI and line tables are looked up without it.
+    load_bias: usize,
+    /// Functions in the ELF symbol tables, which shared libraries have even without DWARF
+    symbols: Vec<Symbol>,
+    /// Addresses covered by the loadable segments
+    range: (usize, usize),
+    /// Address of the .text section and its end
+    text_range: Option<(usize, usize)>,
+    /// Address of the dynamic section, where the dynamic linker leaves a pointer to its state
+    dynamic: Option<usize>,
+    /// Shared libraries loaded into the program. Lookups by address go to the library that
+    /// contains the address.
+    libraries: Vec<SharedLibrary>,
+}
+
+/// A function symbol from .symtab or .dynsym.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub address: usize,
+    pub size: usize,
+}
+
+/// A shared object mapped into the program, with its own symbols.
+pub struct SharedLibrary {
+    pub path: String,
+    pub debug_data: DwarfData,
 }
 
 impl fmt::Debug for DwarfData {
@@ -38,32 +81,251 @@ impl DwarfData {
         } else {
             gimli::RunTimeEndian::Big
         };
+        // stripped binaries may have their DWARF in a separate file
+        let debug_mmap = if object.section_by_name(".debug_info").is_none() {
+            find_debug_file(path, &object)
+                .and_then(|debug_path| fs::File::open(debug_path).ok())
+                .and_then(|debug_file| unsafe { memmap::Mmap::map(&debug_file).ok() })
+        } else {
+            None
+        };
+        let debug_object = debug_mmap
+            .as_ref()
+            .and_then(|debug_mmap| object::File::parse(&**debug_mmap).ok());
+        let dwarf_object = debug_object.as_ref().unwrap_or(&object);
+        let (files, types) = gimli_wrapper::load_file(dwarf_object, endian)?;
+        let mut symbols = load_symbols(&object);
+        if let Some(ref debug_object) = debug_object {
+            // the stripped file may only have .dynsym left
+            symbols.extend(load_symbols(debug_object));
+            symbols.sort_by_key(|sym| sym.address);
+            symbols.dedup_by(|a, b| a.address == b.address && a.name == b.name);
+        }
         Ok(DwarfData {
-            files: gimli_wrapper::load_file(&object, endian)?,
-            addr2line: Context::new(&object).or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            files,
+            types,
+            cfi: gimli_wrapper::CallFrameInfo::load(&object, endian),
+            loaded_sections: load_sections(&object),
+            addr2line: Context::new(dwarf_object)
+                .or_else(|e| Err(gimli_wrapper::Error::from(e)))?,
+            entry: object.entry() as usize,
+            load_bias: 0,
+            symbols,
+            range: load_range(&object),
+            text_range: object.section_by_name(".text").map(|section| {
+                let start = object::ObjectSection::address(&section) as usize;
+                (start, start + object::ObjectSection::size(&section) as usize)
+            }),
+            dynamic: object
+                .section_by_name(".dynamic")
+                .map(|section| object::ObjectSection::address(&section) as usize),
+            libraries: Vec::new(),
         })
     }
 
-    #[allow(dead_code)]
-    fn get_target_file(&self, file: &str) -> Option<&File> {
+    /// Loads the symbols of the shared library at `path`, which was loaded `load_bias` bytes past
+    /// its link-time addresses.
+    pub fn add_library(&mut self, path: &str, load_bias: usize) -> Result<(), Error> {
+        let mut debug_data = DwarfData::from_file(path)?;
+        debug_data.set_load_bias(load_bias);
+        self.libraries.push(SharedLibrary {
+            path: path.to_string(),
+            debug_data,
+        });
+        Ok(())
+    }
+
+    /// Forgets the shared library at `path` after the program unloaded it.
+    pub fn remove_library(&mut self, path: &str) {
+        self.libraries.retain(|lib| lib.path != path);
+    }
+
+    /// Forgets all shared libraries, e.g. because the program is started again.
+    pub fn clear_libraries(&mut self) {
+        self.libraries.clear();
+    }
+
+    pub fn libraries(&self) -> &[SharedLibrary] {
+        &self.libraries
+    }
+
+    /// Returns the shared library whose segments contain `addr`.
+    pub fn get_library_at(&self, addr: usize) -> Option<&SharedLibrary> {
+        self.libraries
+            .iter()
+            .find(|lib| lib.debug_data.range.0 <= addr && addr < lib.debug_data.range.1)
+    }
+
+    /// Returns whether `addr` lies in one of the program's own loadable segments (its code and
+    /// static data, not the stack, heap or shared libraries).
+    pub fn contains_address(&self, addr: usize) -> bool {
+        self.range.0 <= addr && addr < self.range.1
+    }
+
+    /// Returns where the .text section was loaded, as a [start, end) pair.
+    pub fn get_text_range(&self) -> Option<(usize, usize)> {
+        self.text_range
+    }
+
+    /// Returns the address of the dynamic section. None for static executables.
+    pub fn get_dynamic_addr(&self) -> Option<usize> {
+        self.dynamic
+    }
+
+    /// Whether there is any DWARF, as opposed to just a symbol table.
+    pub fn has_debug_info(&self) -> bool {
+        !self.files.is_empty()
+    }
+
+    /// Returns the address of the function symbol `name`, searching the libraries too.
+    pub fn get_addr_for_symbol(&self, name: &str) -> Option<usize> {
+        match self.symbols.iter().find(|sym| sym.name == name) {
+            Some(sym) => Some(sym.address),
+            None => self
+                .libraries
+                .iter()
+                .find_map(|lib| lib.debug_data.get_addr_for_symbol(name)),
+        }
+    }
+
+    /// Returns the link-time address of the program's entry point.
+    pub fn get_entry(&self) -> usize {
+        self.entry
+    }
+
+    pub fn get_load_bias(&self) -> usize {
+        self.load_bias
+    }
+
+    /// Moves every address to where the program was loaded: `load_bias` bytes past its
+    /// link-time address. Only position independent executables have a non-zero bias.
+    pub fn set_load_bias(&mut self, load_bias: usize) {
+        let delta = load_bias.wrapping_sub(self.load_bias);
+        self.load_bias = load_bias;
+        for file in self.files.iter_mut() {
+            for var in file.global_variables.iter_mut() {
+                var.relocate(delta, load_bias);
+            }
+            for func in file.functions.iter_mut() {
+                func.address = func.address.wrapping_add(delta);
+                for var in func.variables.iter_mut() {
+                    var.relocate(delta, load_bias);
+                }
+            }
+            for line in file.lines.iter_mut() {
+                line.address = line.address.wrapping_add(delta);
+            }
+            for (_, addr) in file.inlined_functions.iter_mut() {
+                *addr = addr.wrapping_add(delta);
+            }
+        }
+        for (start, _) in self.loaded_sections.iter_mut() {
+            *start = start.wrapping_add(delta);
+        }
+        for sym in self.symbols.iter_mut() {
+            sym.address = sym.address.wrapping_add(delta);
+        }
+        self.range = (
+            self.range.0.wrapping_add(delta),
+            self.range.1.wrapping_add(delta),
+        );
+        self.text_range = self
+            .text_range
+            .map(|(start, end)| (start.wrapping_add(delta), end.wrapping_add(delta)));
+        self.dynamic = self.dynamic.map(|addr| addr.wrapping_add(delta));
+    }
+
+    /// Finds the compilation unit of source file `file`, given by its name, its full path or
+    /// the path the unit was compiled with.
+    pub fn get_target_file(&self, file: &str) -> Option<&File> {
         self.files.iter().find(|f| {
-            f.name == file || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+            f.name == file
+                || (!file.contains("/") && f.name.ends_with(&format!("/{}", file)))
+                || (!f.name.starts_with('/') && file.ends_with(&format!("/{}", f.name)))
         })
     }
 
-    #[allow(dead_code)]
-    pub fn get_addr_for_line(&self, file: Option<&str>, line_number: usize) -> Option<usize> {
+    /// Returns the addresses to break at for line `line_number` of `file` (the first file if
+    /// None), or the next line with code. A line can have several, e.g. in each function it was
+    /// inlined into; within a function the lowest is_stmt address after the prologue is used.
+    pub fn get_addrs_for_line(&self, file: Option<&str>, line_number: usize) -> Vec<usize> {
         let target_file = match file {
-            Some(filename) => self.get_target_file(filename)?,
-            None => self.files.get(0)?,
+            Some(filename) => self.get_target_file(filename),
+            None => self.files.get(0),
         };
-        Some(
-            target_file
+        let lines: Vec<&Line> = match target_file {
+            Some(target_file) => target_file
                 .lines
                 .iter()
-                .find(|line| line.number >= line_number)?
-                .address,
-        )
+                .filter(|line| line.is_stmt)
+                .collect(),
+            None => return Vec::new(),
+        };
+        let number = match lines
+            .iter()
+            .map(|line| line.number)
+            .filter(|number| *number >= line_number)
+            .min()
+        {
+            Some(number) => number,
+            None => return Vec::new(),
+        };
+        let mut rows: Vec<usize> = lines
+            .iter()
+            .filter(|line| line.number == number)
+            .map(|line| line.address)
+            .collect();
+        rows.sort();
+        let mut functions = Vec::new();
+        let mut addrs = Vec::new();
+        for addr in rows {
+            let func_addr = self
+                .get_function_data_from_addr(addr)
+                .map(|func| func.address);
+            if functions.contains(&func_addr) {
+                continue;
+            }
+            functions.push(func_addr);
+            // the opening line of a function is in its prologue
+            addrs.push(match func_addr {
+                Some(func_addr) => addr.max(self.get_addr_after_prologue(func_addr)),
+                None => addr,
+            });
+        }
+        addrs
+    }
+
+    /// Returns the addresses to break at for function `func_name` in `file` (any file if None):
+    /// after the prologue of each function of that name, and at the start of its inlined copies.
+    pub fn get_addrs_for_function(&self, file: Option<&str>, func_name: &str) -> Vec<usize> {
+        let files: Vec<&File> = match file {
+            Some(filename) => self.get_target_file(filename).into_iter().collect(),
+            None => self.files.iter().collect(),
+        };
+        let mut addrs: Vec<usize> = files
+            .iter()
+            .flat_map(|file| file.functions.iter())
+            .filter(|func| func.name == func_name)
+            .map(|func| self.get_addr_after_prologue(func.address))
+            .chain(
+                files
+                    .iter()
+                    .flat_map(|file| file.inlined_functions.iter())
+                    .filter(|(name, _)| name == func_name)
+                    .map(|(_, addr)| *addr),
+            )
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        if addrs.is_empty() && file.is_none() {
+            addrs = self
+                .libraries
+                .iter()
+                .map(|lib| lib.debug_data.get_addrs_for_function(None, func_name))
+                .find(|addrs| !addrs.is_empty())
+                .unwrap_or_default();
+        }
+        addrs
     }
 
     #[allow(dead_code)]
@@ -82,35 +344,464 @@ impl DwarfData {
                         return Some(func.address);
                     }
                 }
-                None
+                self.libraries
+                    .iter()
+                    .find_map(|lib| lib.debug_data.get_addr_for_function(None, func_name))
             }
         }
     }
 
+    /// Returns the address of the first line after the prologue of the function starting at
+    /// `func_addr`, which is where arguments and the frame pointer have been set up. Falls back
+    /// to `func_addr` if the line table doesn't tell.
+    pub fn get_addr_after_prologue(&self, func_addr: usize) -> usize {
+        if let Some(lib) = self.get_library_at(func_addr) {
+            return lib.debug_data.get_addr_after_prologue(func_addr);
+        }
+        let func = match self.get_function_data_from_addr(func_addr) {
+            Some(func) => func,
+            None => return func_addr,
+        };
+        let mut rows: Vec<&Line> = self
+            .files
+            .iter()
+            .flat_map(|file| file.lines.iter())
+            .filter(|line| line.is_stmt)
+            .filter(|line| line.address >= func.address && line.address < func.address + func.text_length)
+            .collect();
+        // stable, so rows at the same address stay in line table order
+        rows.sort_by_key(|line| line.address);
+        // the body starts at the first statement that isn't on the opening line. Optimized code
+        // may have no prologue, in which case that is at func_addr already.
+        let opening_line = rows.first().map(|line| line.number);
+        rows.iter()
+            .find(|line| Some(line.number) != opening_line)
+            .or_else(|| rows.iter().find(|line| line.address > func.address))
+            .map(|line| line.address)
+            .unwrap_or(func_addr)
+    }
+
     #[allow(dead_code)]
     pub fn get_line_from_addr(&self, curr_addr: usize) -> Option<Line> {
+        if let Some(lib) = self.get_library_at(curr_addr) {
+            return lib.debug_data.get_line_from_addr(curr_addr);
+        }
         let location = self
             .addr2line
-            .find_location(curr_addr.try_into().unwrap())
+            .find_location(curr_addr.wrapping_sub(self.load_bias).try_into().unwrap())
             .ok()??;
         Some(Line {
             file: location.file?.to_string(),
             number: location.line?.try_into().unwrap(),
             address: curr_addr,
+            is_stmt: true,
         })
     }
 
     #[allow(dead_code)]
     pub fn get_function_from_addr(&self, curr_addr: usize) -> Option<String> {
+        if let Some(lib) = self.get_library_at(curr_addr) {
+            return lib.debug_data.get_function_from_addr(curr_addr);
+        }
+        self.get_dwarf_function_name(curr_addr).or_else(|| {
+            self.get_symbol_from_addr(curr_addr)
+                .map(|(sym, _)| sym.name.clone())
+        })
+    }
+
+    fn get_dwarf_function_name(&self, curr_addr: usize) -> Option<String> {
         let frame = self
             .addr2line
-            .find_frames(curr_addr.try_into().unwrap())
+            .find_frames(curr_addr.wrapping_sub(self.load_bias).try_into().unwrap())
             .ok()?
             .next()
             .ok()??;
         Some(frame.function?.raw_name().ok()?.to_string())
     }
 
+    /// Returns the function symbol containing `curr_addr`, and how far into it the address is.
+    pub fn get_symbol_from_addr(&self, curr_addr: usize) -> Option<(&Symbol, usize)> {
+        if let Some(lib) = self.get_library_at(curr_addr) {
+            return lib.debug_data.get_symbol_from_addr(curr_addr);
+        }
+        // symbols are sorted by address, so the last one starting at or before curr_addr is the
+        // only candidate (along with its aliases)
+        let end = self
+            .symbols
+            .iter()
+            .position(|sym| sym.address > curr_addr)
+            .unwrap_or(self.symbols.len());
+        let start = self.symbols[..end].last()?.address;
+        let sym = self.symbols[..end]
+            .iter()
+            .filter(|sym| sym.address == start)
+            .find(|sym| curr_addr < sym.address + sym.size.max(1))?;
+        Some((sym, curr_addr - sym.address))
+    }
+
+    /// Formats `curr_addr` as func+0x1a using the symbol tables, for code without line info.
+    pub fn get_symbol_location(&self, curr_addr: usize) -> Option<String> {
+        match self.get_symbol_from_addr(curr_addr)? {
+            (sym, 0) => Some(sym.name.clone()),
+            (sym, offset) => Some(format!("{}+{:#x}", sym.name, offset)),
+        }
+    }
+
+    /// Reads up to `len` bytes at `addr` from the code or initialized data in the executable file.
+    pub fn get_file_bytes(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        if let Some(lib) = self.get_library_at(addr) {
+            return lib.debug_data.get_file_bytes(addr, len);
+        }
+        let (start, data) = self
+            .loaded_sections
+            .iter()
+            .find(|(start, data)| *start <= addr && addr < start + data.len())?;
+        let offset = addr - start;
+        let end = (offset + len).min(data.len());
+        Some(data[offset..end].to_vec())
+    }
+
+    /// Returns the CFI rule for unwinding the frame of the code at `curr_addr`.
+    pub fn get_frame_rule(&self, curr_addr: usize) -> Option<FrameRule> {
+        if let Some(lib) = self.get_library_at(curr_addr) {
+            return lib.debug_data.get_frame_rule(curr_addr);
+        }
+        self.cfi
+            .find_rule(curr_addr.wrapping_sub(self.load_bias) as u64)
+    }
+
+    /// Returns the function whose code contains `curr_addr`.
+    pub fn get_function_data_from_addr(&self, curr_addr: usize) -> Option<&Function> {
+        if let Some(lib) = self.get_library_at(curr_addr) {
+            return lib.debug_data.get_function_data_from_addr(curr_addr);
+        }
+        self.files.iter().flat_map(|file| file.functions.iter()).find(|func| {
+            func.address <= curr_addr && curr_addr < func.address + func.text_length
+        })
+    }
+
+    pub fn get_type(&self, id: usize) -> Option<&Type> {
+        self.types.get(&id)
+    }
+
+    /// Finds a type by its C name, e.g. "int", "struct point" or "node_t".
+    pub fn get_type_by_name(&self, name: &str) -> Option<&Type> {
+        self.get_type(self.get_type_id_by_name(name)?)
+    }
+
+    /// Returns the id of the type with the given C name.
+    pub fn get_type_id_by_name(&self, name: &str) -> Option<usize> {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        // prefer complete definitions over declarations of the same struct
+        self.types
+            .iter()
+            .filter(|(_, ty)| ty.name == name)
+            .max_by_key(|(id, ty)| (ty.size, std::cmp::Reverse(**id)))
+            .map(|(id, _)| *id)
+    }
+
+    /// Finds an enumerator (a constant of an enum type) by name. Returns its type and value.
+    pub fn get_enumerator(&self, name: &str) -> Option<(&Type, i64)> {
+        self.types.values().find_map(|ty| match ty.kind {
+            TypeKind::Enum(ref enumerators) => enumerators
+                .iter()
+                .find(|(enumerator, _)| enumerator == name)
+                .map(|(_, value)| (ty, *value)),
+            _ => None,
+        })
+    }
+
+    /// Returns the type of a pointer to `ty`.
+    pub fn pointer_to(&self, ty: &Type) -> Type {
+        let name = if ty.name.ends_with('*') {
+            format!("{}*", ty.name)
+        } else {
+            format!("{} *", ty.name)
+        };
+        Type {
+            name,
+            size: size_of::<usize>(),
+            kind: TypeKind::Pointer(self.get_type_id_by_name(&ty.name)),
+        }
+    }
+
+    /// Returns the type of the elements of an array, or of what a pointer points to. For
+    /// multidimensional arrays, the elements are arrays themselves.
+    pub fn get_element_type(&self, ty: &Type) -> Option<Type> {
+        let (element_id, dims) = match self.strip_aliases(ty)?.kind {
+            TypeKind::Array(element_id, ref dims) => (element_id, dims),
+            _ => return self.get_pointee(ty).cloned(),
+        };
+        let element = self.get_type(element_id)?;
+        if dims.len() < 2 {
+            return Some(element.clone());
+        }
+        let dims = dims[1..].to_vec();
+        let name = dims
+            .iter()
+            .fold(element.name.clone(), |name, dim| match dim {
+                Some(len) => format!("{}[{}]", name, len),
+                None => format!("{}[]", name),
+            });
+        let size = element.size * dims.iter().map(|dim| dim.unwrap_or(0)).product::<usize>();
+        Some(Type {
+            name: name.replacen('[', " [", 1),
+            size,
+            kind: TypeKind::Array(element_id, dims),
+        })
+    }
+
+    /// Finds the member called `name` of a struct or union, looking inside anonymous members.
+    /// The offset of the member returned is from the start of `ty`.
+    pub fn get_member(&self, ty: &Type, name: &str) -> Option<(Member, &Type)> {
+        self.get_member_at_depth(ty, name, 0)
+    }
+
+    fn get_member_at_depth(&self, ty: &Type, name: &str, depth: usize) -> Option<(Member, &Type)> {
+        let members = match self.strip_aliases(ty)?.kind {
+            TypeKind::Struct(ref members) | TypeKind::Union(ref members) => members,
+            _ => return None,
+        };
+        for member in members {
+            let member_type = self.get_type(member.type_id)?;
+            if member.name == name {
+                return Some((member.clone(), member_type));
+            }
+            if member.name.is_empty() && depth < MAX_TYPE_DEPTH {
+                if let Some((mut inner, inner_type)) =
+                    self.get_member_at_depth(member_type, name, depth + 1)
+                {
+                    inner.offset += member.offset;
+                    inner.bits = inner
+                        .bits
+                        .map(|(bit_offset, bit_size)| (bit_offset + 8 * member.offset, bit_size));
+                    return Some((inner, inner_type));
+                }
+            }
+        }
+        None
+    }
+
+    /// Looks through typedefs and qualifiers. Returns None for void.
+    pub fn strip_aliases<'a>(&'a self, ty: &'a Type) -> Option<&'a Type> {
+        let mut ty = ty;
+        // the depth limit protects against malformed cyclic DWARF
+        for _ in 0..MAX_TYPE_DEPTH {
+            match ty.kind {
+                TypeKind::Alias(Some(target)) | TypeKind::Qualified(Some(target)) => {
+                    ty = self.get_type(target)?
+                }
+                TypeKind::Alias(None) | TypeKind::Qualified(None) => return None,
+                _ => return Some(ty),
+            }
+        }
+        None
+    }
+
+    /// Returns the type a pointer (or array) points to.
+    pub fn get_pointee(&self, ty: &Type) -> Option<&Type> {
+        match self.strip_aliases(ty)?.kind {
+            TypeKind::Pointer(Some(pointee)) | TypeKind::Array(pointee, _) => {
+                self.get_type(pointee)
+            }
+            _ => None,
+        }
+    }
+
+    /// Pretty-prints the raw bytes of a value of type `ty`, gdb style: structs as
+    /// {a = 1, b = 2}, arrays as {1, 2, 3}. `read_memory` is used to show what char pointers
+    /// point to.
+    pub fn format_value(
+        &self,
+        ty: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+    ) -> String {
+        self.format_value_at_depth(ty, bytes, read_memory, 0)
+    }
+
+    fn format_value_at_depth(
+        &self,
+        ty: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        depth: usize,
+    ) -> String {
+        if depth > MAX_TYPE_DEPTH {
+            return "...".to_string();
+        }
+        let ty = match self.strip_aliases(ty) {
+            Some(ty) => ty,
+            None => return "<void>".to_string(),
+        };
+        if bytes.len() < ty.size {
+            return "<unavailable>".to_string();
+        }
+        match ty.kind {
+            TypeKind::Base => ty.format_value(bytes),
+            TypeKind::Pointer(pointee) => {
+                let addr = ty.integer_value(bytes).unwrap_or(0) as usize;
+                let pointee = pointee
+                    .and_then(|id| self.get_type(id))
+                    .and_then(|pointee| self.strip_aliases(pointee));
+                match pointee {
+                    Some(pointee) if addr != 0 && pointee.is_char() => {
+                        format!("{:#x} {}", addr, format_c_string(addr, read_memory))
+                    }
+                    Some(Type {
+                        kind: TypeKind::Function(_, _),
+                        ..
+                    }) => match self.get_function_data_from_addr(addr) {
+                        Some(func) if func.address == addr => {
+                            format!("{:#x} <{}>", addr, func.name)
+                        }
+                        _ => format!("{:#x}", addr),
+                    },
+                    _ => format!("{:#x}", addr),
+                }
+            }
+            TypeKind::Struct(ref members) | TypeKind::Union(ref members) => {
+                let fields: Vec<String> = members
+                    .iter()
+                    .map(|member| {
+                        let value = match self.get_type(member.type_id) {
+                            Some(member_type) => {
+                                self.format_member(member, member_type, bytes, read_memory, depth)
+                            }
+                            None => "<unknown type>".to_string(),
+                        };
+                        if member.name.is_empty() {
+                            value
+                        } else {
+                            format!("{} = {}", member.name, value)
+                        }
+                    })
+                    .collect();
+                format!("{{{}}}", fields.join(", "))
+            }
+            TypeKind::Array(element, ref dims) => {
+                let element = match self.get_type(element) {
+                    Some(element) => element,
+                    None => return "<unknown type>".to_string(),
+                };
+                self.format_array(element, dims, bytes, read_memory, depth)
+            }
+            TypeKind::Enum(ref enumerators) => {
+                let value = ty.integer_value(bytes).unwrap_or(0);
+                match enumerators.iter().find(|(_, val)| *val == value) {
+                    Some((name, _)) => name.clone(),
+                    None => value.to_string(),
+                }
+            }
+            TypeKind::Function(_, _) => "{<text variable>}".to_string(),
+            TypeKind::Alias(_) | TypeKind::Qualified(_) => unreachable!(),
+        }
+    }
+
+    fn format_member(
+        &self,
+        member: &Member,
+        member_type: &Type,
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        depth: usize,
+    ) -> String {
+        let member_bytes = self.get_member_bytes(member, member_type, bytes);
+        self.format_value_at_depth(member_type, &member_bytes, read_memory, depth + 1)
+    }
+
+    /// Extracts the raw bytes of a member from those of the struct it belongs to. Bit fields are
+    /// returned as if they were a whole value of their type.
+    pub fn get_member_bytes(&self, member: &Member, member_type: &Type, bytes: &[u8]) -> Vec<u8> {
+        let (bit_offset, bit_size) = match member.bits {
+            Some(bits) => bits,
+            None => {
+                let end = (member.offset + member_type.size).min(bytes.len());
+                let start = member.offset.min(end);
+                return bytes[start..end].to_vec();
+            }
+        };
+        let mut raw = [0u8; 16];
+        let first_byte = (bit_offset / 8).min(bytes.len());
+        let len = (bytes.len().saturating_sub(first_byte)).min(16);
+        raw[..len].copy_from_slice(&bytes[first_byte..first_byte + len]);
+        let mut value = (u128::from_le_bytes(raw) >> (bit_offset % 8)) & ((1 << bit_size) - 1);
+        let signed = self.strip_aliases(member_type).map_or(false, |ty| {
+            ty.integer_value(&[0xff; 8]).map_or(false, |v| v < 0)
+        });
+        if signed && value >> (bit_size - 1) & 1 == 1 {
+            // sign-extend
+            value |= !0 << bit_size;
+        }
+        let size = member_type.size.min(16);
+        value.to_le_bytes()[..size].to_vec()
+    }
+
+    fn format_array(
+        &self,
+        element: &Type,
+        dims: &[Option<usize>],
+        bytes: &[u8],
+        read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>,
+        depth: usize,
+    ) -> String {
+        let len = match dims.first() {
+            Some(Some(len)) => *len,
+            Some(None) => return "<unknown length>".to_string(),
+            None => return self.format_value_at_depth(element, bytes, read_memory, depth + 1),
+        };
+        let stride = element.size
+            * dims[1..]
+                .iter()
+                .map(|dim| dim.unwrap_or(0))
+                .product::<usize>();
+        if dims.len() == 1 && self.strip_aliases(element).map_or(false, |ty| ty.is_char()) {
+            let text = &bytes[..len.min(bytes.len())];
+            let text = match text.iter().position(|b| *b == 0) {
+                Some(nul) => &text[..nul],
+                None => text,
+            };
+            let escaped: String = text.iter().map(|b| escape_char(*b)).collect();
+            return format!("\"{}\"", escaped);
+        }
+        let mut elements = Vec::new();
+        for i in 0..len.min(MAX_ARRAY_ELEMENTS) {
+            let start = (i * stride).min(bytes.len());
+            let end = ((i + 1) * stride).min(bytes.len());
+            elements.push(self.format_array(
+                element,
+                &dims[1..],
+                &bytes[start..end],
+                read_memory,
+                depth + 1,
+            ));
+        }
+        if len > MAX_ARRAY_ELEMENTS {
+            elements.push("...".to_string());
+        }
+        format!("{{{}}}", elements.join(", "))
+    }
+
+    /// Returns the global variable called `name`, searching every compilation unit.
+    pub fn get_global_variable(&self, name: &str) -> Option<&Variable> {
+        self.global_variables().into_iter().find(|var| var.name == name)
+    }
+
+    /// Returns the global variable whose storage contains `addr`.
+    pub fn get_global_variable_from_addr(&self, addr: usize) -> Option<&Variable> {
+        self.global_variables().into_iter().find(|var| match var.location {
+            Location::Address(start) => start <= addr && addr < start + var.entity_type.size.max(1),
+            _ => false,
+        })
+    }
+
+    pub fn global_variables(&self) -> Vec<&Variable> {
+        self.files
+            .iter()
+            .flat_map(|file| file.global_variables.iter())
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub fn print(&self) {
         for file in &self.files {
@@ -148,10 +839,189 @@ impl DwarfData {
     }
 }
 
+fn load_sections(object: &object::File) -> Vec<(usize, Vec<u8>)> {
+    [".init", ".plt", ".plt.sec", ".text", ".fini", ".rodata", ".data"]
+        .iter()
+        .filter_map(|name| {
+            let addr = object::ObjectSection::address(&object.section_by_name(name)?);
+            let data = object.section_data_by_name(name)?;
+            Some((addr as usize, data.into_owned()))
+        })
+        .collect()
+}
+
+/// Collects the function symbols of both symbol tables.
+fn load_symbols(object: &object::File) -> Vec<Symbol> {
+    let mut symbols: Vec<Symbol> = object
+        .symbols()
+        .chain(object.dynamic_symbols())
+        .filter(|(_, sym)| sym.kind() == object::SymbolKind::Text && !sym.is_undefined())
+        .filter_map(|(_, sym)| {
+            Some(Symbol {
+                name: sym.name()?.to_string(),
+                address: sym.address() as usize,
+                size: sym.size() as usize,
+            })
+        })
+        .filter(|sym| sym.address != 0 && !sym.name.is_empty())
+        .collect();
+    symbols.sort_by_key(|sym| sym.address);
+    symbols
+}
+
+/// Where distributions install separate debug files
+const DEBUG_DIR: &str = "/usr/lib/debug";
+/// Note type of the build ID
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Looks for the separate debug file of the binary at `path`, first by its build ID, then by the
+/// name and CRC in its .gnu_debuglink section, in the places gdb looks.
+fn find_debug_file(path: &str, object: &object::File) -> Option<String> {
+    if let Some(build_id) = object
+        .section_data_by_name(".note.gnu.build-id")
+        .and_then(|note| parse_build_id(&note))
+    {
+        let hex: String = build_id.iter().map(|b| format!("{:02x}", b)).collect();
+        if hex.len() > 2 {
+            let candidate = format!("{}/.build-id/{}/{}.debug", DEBUG_DIR, &hex[..2], &hex[2..]);
+            if Path::new(&candidate).is_file() {
+                return Some(candidate);
+            }
+        }
+    }
+    let debuglink = object.section_data_by_name(".gnu_debuglink")?;
+    let name_len = debuglink.iter().position(|b| *b == 0)?;
+    let name = std::str::from_utf8(&debuglink[..name_len]).ok()?;
+    // the CRC follows the name, 4 byte aligned
+    let crc_offset = (name_len + 4) & !3;
+    let crc = u32::from_le_bytes(debuglink.get(crc_offset..crc_offset + 4)?.try_into().ok()?);
+    let dir = fs::canonicalize(path)
+        .ok()?
+        .parent()?
+        .to_string_lossy()
+        .to_string();
+    let candidates = [
+        format!("{}/{}", dir, name),
+        format!("{}/.debug/{}", dir, name),
+        format!("{}{}/{}", DEBUG_DIR, dir, name),
+    ];
+    candidates
+        .iter()
+        .find(|candidate| fs::read(candidate).map_or(false, |data| crc32(&data) == crc))
+        .cloned()
+}
+
+/// Returns the descriptor of the NT_GNU_BUILD_ID note in `note`.
+fn parse_build_id(note: &[u8]) -> Option<Vec<u8>> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_le_bytes(
+            note.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let name_size = read_u32(0)? as usize;
+    let desc_size = read_u32(4)? as usize;
+    if read_u32(8)? != NT_GNU_BUILD_ID {
+        return None;
+    }
+    let desc_start = 12 + ((name_size + 3) & !3);
+    Some(note.get(desc_start..desc_start + desc_size)?.to_vec())
+}
+
+/// The CRC-32 that .gnu_debuglink uses to check that a debug file belongs to the binary.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut crc = i as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+        *entry = crc;
+    }
+    !data.iter().fold(!0u32, |crc, byte| {
+        table[((crc ^ *byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Returns the addresses covered by the loadable segments, as a [start, end) pair.
+fn load_range(object: &object::File) -> (usize, usize) {
+    let segments: Vec<(u64, u64)> = object
+        .segments()
+        .map(|seg| {
+            let addr = object::ObjectSegment::address(&seg);
+            (addr, addr + object::ObjectSegment::size(&seg))
+        })
+        .collect();
+    let start = segments.iter().map(|seg| seg.0).min().unwrap_or(0);
+    let end = segments.iter().map(|seg| seg.1).max().unwrap_or(0);
+    (start as usize, end as usize)
+}
+
+/// Like gdb's default "print elements"
+const MAX_ARRAY_ELEMENTS: usize = 200;
+
+/// Formats the NUL-terminated string at `addr` as a C string literal.
+fn format_c_string(addr: usize, read_memory: &dyn Fn(usize, usize) -> Option<Vec<u8>>) -> String {
+    let bytes = match read_memory(addr, MAX_ARRAY_ELEMENTS) {
+        Some(ref bytes) if !bytes.is_empty() => bytes.clone(),
+        _ => return format!("<error: Cannot access memory at address {:#x}>", addr),
+    };
+    let (text, truncated) = match bytes.iter().position(|b| *b == 0) {
+        Some(nul) => (&bytes[..nul], false),
+        None => (&bytes[..], true),
+    };
+    let escaped: String = text.iter().map(|b| escape_char(*b)).collect();
+    format!("\"{}\"{}", escaped, if truncated { "..." } else { "" })
+}
+
+/// A member of a struct or union.
+#[derive(Debug, Clone)]
+pub struct Member {
+    /// Empty for anonymous structs and unions
+    pub name: String,
+    pub type_id: usize,
+    /// Offset from the start of the struct, in bytes
+    pub offset: usize,
+    /// For bit fields, the offset from the start of the struct and the size, in bits
+    pub bits: Option<(usize, usize)>,
+}
+
+/// What kind of type a Type is. Other types are referred to by id, which is the .debug_info
+/// offset of their DIE, since DWARF types can refer to each other in cycles (and forwards).
+#[derive(Debug, Clone)]
+pub enum TypeKind {
+    /// int, char, double, ...; values are formatted according to the name
+    Base,
+    /// The pointee, or None for void *
+    Pointer(Option<usize>),
+    Struct(Vec<Member>),
+    Union(Vec<Member>),
+    /// Element type and the length of each dimension (None if unknown, as in `int a[]`)
+    Array(usize, Vec<Option<usize>>),
+    /// Enumerator names and values
+    Enum(Vec<(String, i64)>),
+    /// A typedef: the aliased type, or None for void
+    Alias(Option<usize>),
+    /// A const/volatile/restrict qualified type, or None for qualified void
+    Qualified(Option<usize>),
+    /// Return type (None for void) and parameter types
+    Function(Option<usize>, Vec<usize>),
+}
+
+impl Default for TypeKind {
+    fn default() -> Self {
+        TypeKind::Base
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct Type {
     pub name: String,
     pub size: usize,
+    pub kind: TypeKind,
 }
 
 impl Type {
@@ -159,21 +1029,275 @@ impl Type {
         Type {
             name: name,
             size: size,
+            kind: TypeKind::Base,
         }
     }
+
+    pub fn is_char(&self) -> bool {
+        matches!(self.kind, TypeKind::Base) && self.size == 1 && self.name.contains("char")
+    }
+
+    /// Returns true for types that hold a single number: base types, pointers and enums.
+    pub fn is_scalar(&self) -> bool {
+        matches!(
+            self.kind,
+            TypeKind::Base | TypeKind::Pointer(_) | TypeKind::Enum(_)
+        )
+    }
+
+    /// Builds the type named by a C cast such as "int" or "unsigned char *". Only base types and
+    /// pointers are understood.
+    pub fn from_c_name(name: &str) -> Option<Self> {
+        let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+        if name.ends_with('*') {
+            let pointee = name.trim_end_matches('*').trim_end();
+            return Some(Type {
+                name: format!("{} *", pointee),
+                size: 8,
+                kind: TypeKind::Pointer(None),
+            });
+        }
+        let size = match name.trim_start_matches("unsigned ").trim_start_matches("signed ") {
+            "char" | "_Bool" | "bool" => 1,
+            "short" | "short int" => 2,
+            "int" | "unsigned" | "signed" | "float" => 4,
+            "long" | "long int" | "long long" | "long long int" | "double" => 8,
+            "long double" => 16,
+            _ => return None,
+        };
+        Some(Type::new(name, size))
+    }
+
+    pub fn is_float(&self) -> bool {
+        let is_float_name = self.name.contains("float") || self.name.contains("double");
+        matches!(self.kind, TypeKind::Base) && is_float_name
+    }
+
+    /// Encodes an integer as the raw (little-endian) bytes of a value of this type, truncating it
+    /// to the type's size.
+    pub fn encode_integer(&self, value: i64) -> Vec<u8> {
+        let mut bytes = value.to_le_bytes().to_vec();
+        bytes.resize(self.size, if value < 0 { 0xff } else { 0 });
+        bytes
+    }
+
+    /// Encodes a number as the raw (little-endian) bytes of a value of this floating point type.
+    pub fn encode_float(&self, value: f64) -> Option<Vec<u8>> {
+        match self.size {
+            4 => Some((value as f32).to_le_bytes().to_vec()),
+            8 => Some(value.to_le_bytes().to_vec()),
+            16 | 10 => {
+                let mut bytes = f64_to_extended(value).to_vec();
+                bytes.resize(self.size, 0);
+                Some(bytes)
+            }
+            _ => None,
+        }
+    }
+
+    /// Interprets the raw (little-endian) bytes of a value of this type as an integer. Returns
+    /// None for floating point types.
+    pub fn integer_value(&self, bytes: &[u8]) -> Option<i64> {
+        if self.is_float() || !self.is_scalar() {
+            return None;
+        }
+        if self.size == 0 || self.size > 8 || bytes.len() < self.size {
+            return None;
+        }
+        let mut raw = [0u8; 8];
+        raw[..self.size].copy_from_slice(&bytes[..self.size]);
+        let unsigned = u64::from_le_bytes(raw);
+        let is_unsigned = self.name.contains("unsigned") || self.name == "_Bool";
+        if matches!(self.kind, TypeKind::Pointer(_)) || is_unsigned {
+            Some(unsigned as i64)
+        } else {
+            let shift = 64 - 8 * self.size as u32;
+            Some(((unsigned << shift) as i64) >> shift)
+        }
+    }
+
+    /// Interprets the raw (little-endian) bytes of a value of this floating point type.
+    pub fn float_value(&self, bytes: &[u8]) -> Option<f64> {
+        if !self.is_float() || bytes.len() < self.size {
+            return None;
+        }
+        match self.size {
+            4 => Some(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64),
+            8 => {
+                let mut raw = [0u8; 8];
+                raw.copy_from_slice(&bytes[..8]);
+                Some(f64::from_le_bytes(raw))
+            }
+            16 | 10 => Some(extended_to_f64(bytes)),
+            _ => None,
+        }
+    }
+
+    /// Formats the raw (little-endian) bytes of a value of this type. The kind of value is
+    /// guessed from the type name, since only base types and pointers are recorded.
+    pub fn format_value(&self, bytes: &[u8]) -> String {
+        if bytes.len() < self.size || self.size == 0 {
+            return "<unavailable>".to_string();
+        }
+        let bytes = &bytes[..self.size];
+        let mut raw = [0u8; 8];
+        let len = bytes.len().min(8);
+        raw[..len].copy_from_slice(&bytes[..len]);
+        let unsigned = u64::from_le_bytes(raw);
+        // sign-extend from the type's width
+        let shift = 64 - 8 * len as u32;
+        let signed = ((unsigned << shift) as i64) >> shift;
+
+        if self.name.ends_with('*') {
+            format!("{:#x}", unsigned)
+        } else if self.is_float() {
+            match self.size {
+                4 => format_float(f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+                8 => format_float(f64::from_le_bytes(raw)),
+                // x87 extended precision, which is what "long double" is on x86-64
+                16 | 10 => format_float(extended_to_f64(bytes)),
+                _ => "<unsupported float>".to_string(),
+            }
+        } else if self.name == "_Bool" || self.name == "bool" {
+            (unsigned != 0).to_string()
+        } else if self.name.contains("char") && self.size == 1 {
+            let value = if self.name.contains("unsigned") {
+                unsigned as i64
+            } else {
+                signed
+            };
+            format!("{} '{}'", value, escape_char(bytes[0]))
+        } else if self.name.contains("unsigned") {
+            format!("{}", unsigned)
+        } else {
+            format!("{}", signed)
+        }
+    }
+}
+
+/// Formats a float like %g does, switching to scientific notation for very large or small values.
+fn format_float<T: Into<f64> + fmt::Display + fmt::LowerExp + Copy>(value: T) -> String {
+    let magnitude = value.into().abs();
+    if magnitude != 0.0 && (magnitude < 1e-4 || magnitude >= 1e16) {
+        format!("{:e}", value)
+    } else {
+        format!("{}", value)
+    }
+}
+
+/// Converts an 80-bit x87 extended precision float to an f64 (losing some precision).
+fn extended_to_f64(bytes: &[u8]) -> f64 {
+    let mut mantissa_bytes = [0u8; 8];
+    mantissa_bytes.copy_from_slice(&bytes[..8]);
+    let mantissa = u64::from_le_bytes(mantissa_bytes);
+    let sign_exp = u16::from_le_bytes([bytes[8], bytes[9]]);
+    let sign = if sign_exp & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = (sign_exp & 0x7fff) as i32;
+    if exponent == 0 && mantissa == 0 {
+        return sign * 0.0;
+    }
+    if exponent == 0x7fff {
+        return if mantissa << 1 == 0 {
+            sign * f64::INFINITY
+        } else {
+            f64::NAN
+        };
+    }
+    // the integer bit is explicit, so the mantissa is read as 1.63 fixed point
+    sign * (mantissa as f64 / (1u64 << 63) as f64) * 2f64.powi(exponent - 16383)
+}
+
+/// Converts an f64 to an 80-bit x87 extended precision float.
+fn f64_to_extended(value: f64) -> [u8; 10] {
+    let bits = value.to_bits();
+    let sign = ((bits >> 63) as u16) << 15;
+    let exponent = ((bits >> 52) & 0x7ff) as i32;
+    let fraction = bits & ((1 << 52) - 1);
+    let (sign_exp, mantissa) = if exponent == 0 && fraction == 0 {
+        (sign, 0)
+    } else if exponent == 0x7ff {
+        (sign | 0x7fff, (1 << 63) | (fraction << 11))
+    } else if exponent == 0 {
+        // subnormal: normalize it, since extended precision has the range for it
+        let shift = fraction.leading_zeros() as i32;
+        let exponent = 1 - 1023 + 16383 - (shift - 11);
+        (sign | exponent as u16, fraction << shift)
+    } else {
+        let exponent = exponent - 1023 + 16383;
+        (sign | exponent as u16, (1 << 63) | (fraction << 11))
+    };
+    let mut bytes = [0u8; 10];
+    bytes[..8].copy_from_slice(&mantissa.to_le_bytes());
+    bytes[8..].copy_from_slice(&sign_exp.to_le_bytes());
+    bytes
 }
 
 #[derive(Clone)]
 pub enum Location {
     Address(usize),
     FramePointerOffset(isize),
+    /// Any other DWARF location expression
+    Expression(DwarfExpression),
+    /// A location list: a different expression for each range of pc values. An empty list means
+    /// the value was optimized out.
+    List(Vec<(usize, usize, DwarfExpression)>),
+    /// The value itself (DW_AT_const_value), for variables that were optimized into constants
+    Constant(Vec<u8>),
+}
+
+impl Location {
+    /// Works out where the value lives in the given frame.
+    pub fn evaluate(&self, frame: &dyn FrameState) -> Place {
+        match self {
+            Location::Address(addr) => Place::Memory(*addr),
+            Location::FramePointerOffset(offset) => {
+                Place::Memory((frame.cfa() as isize + offset) as usize)
+            }
+            Location::Expression(expression) => expression.evaluate(frame),
+            Location::List(entries) => entries
+                .iter()
+                .find(|(start, end, _)| *start <= frame.pc() && frame.pc() < *end)
+                .map_or(Place::OptimizedOut, |(_, _, expression)| {
+                    expression.evaluate(frame)
+                }),
+            Location::Constant(bytes) => Place::Value(bytes.clone()),
+        }
+    }
+
+    /// Moves the addresses in the location by `delta`, for a program loaded `load_bias` bytes
+    /// past its link-time addresses.
+    fn relocate(&mut self, delta: usize, load_bias: usize) {
+        match self {
+            Location::Address(addr) => *addr = addr.wrapping_add(delta),
+            Location::Expression(expression) => expression.set_load_bias(load_bias),
+            Location::List(entries) => {
+                for (start, end, expression) in entries.iter_mut() {
+                    *start = start.wrapping_add(delta);
+                    *end = end.wrapping_add(delta);
+                    expression.set_load_bias(load_bias);
+                }
+            }
+            Location::FramePointerOffset(_) | Location::Constant(_) => {}
+        }
+    }
+
+    /// Returns the address of a global, which doesn't depend on any frame.
+    pub fn static_address(&self) -> Option<usize> {
+        match *self {
+            Location::Address(addr) => Some(addr),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Display for Location {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match *self {
+        match self {
             Location::Address(addr) => write!(f, "Address({:#x})", addr),
             Location::FramePointerOffset(offset) => write!(f, "FramePointerOffset({})", offset),
+            Location::Expression(_) => write!(f, "Expression"),
+            Location::List(entries) => write!(f, "List({} entries)", entries.len()),
+            Location::Constant(bytes) => write!(f, "Constant({:?})", bytes),
         }
     }
 }
@@ -184,6 +1308,29 @@ impl fmt::Debug for Location {
     }
 }
 
+/// Where a variable's value lives at a particular point in the program.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Place {
+    Memory(usize),
+    /// A DWARF register number
+    Register(u16),
+    /// The value was computed; it doesn't live anywhere
+    Value(Vec<u8>),
+    /// Split across several places, each holding the given number of bytes
+    Pieces(Vec<(Place, usize)>),
+    OptimizedOut,
+}
+
+/// The state of one stack frame, as needed to evaluate a location expression.
+pub trait FrameState {
+    fn pc(&self) -> usize;
+    /// Canonical frame address, which gcc also uses as the frame base
+    fn cfa(&self) -> usize;
+    /// Reads a register by DWARF register number. Returns None if it isn't known in this frame.
+    fn register(&self, register: u16) -> Option<u64>;
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>>;
+}
+
 // For variables and formal parameters
 #[derive(Debug, Clone)]
 pub struct Variable {
@@ -191,6 +1338,27 @@ pub struct Variable {
     pub entity_type: Type,
     pub location: Location,
     pub line_number: usize, // Line number in source file
+    pub is_parameter: bool,
+    /// Code ranges of the enclosing lexical block, or empty if visible in the whole function
+    pub scope: Vec<(usize, usize)>,
+}
+
+impl Variable {
+    fn relocate(&mut self, delta: usize, load_bias: usize) {
+        self.location.relocate(delta, load_bias);
+        for (start, end) in self.scope.iter_mut() {
+            *start = start.wrapping_add(delta);
+            *end = end.wrapping_add(delta);
+        }
+    }
+
+    pub fn in_scope(&self, pc: usize) -> bool {
+        self.scope.is_empty()
+            || self
+                .scope
+                .iter()
+                .any(|(start, end)| *start <= pc && pc < *end)
+    }
 }
 
 #[derive(Debug, Default, Clone)]
@@ -208,6 +1376,8 @@ pub struct File {
     pub global_variables: Vec<Variable>,
     pub functions: Vec<Function>,
     pub lines: Vec<Line>,
+    /// Where each inlined copy of a function starts, by the name of the function
+    pub inlined_functions: Vec<(String, usize)>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -215,6 +1385,8 @@ pub struct Line {
     pub file: String,
     pub number: usize,
     pub address: usize,
+    /// Whether the row is a recommended breakpoint location for the line
+    pub is_stmt: bool,
 }
 
 impl fmt::Display for Line {
@@ -222,5 +1394,3 @@ impl fmt::Display for Line {
         write!(f, "{}:{}", self.file, self.number)
     }
 }
-
-
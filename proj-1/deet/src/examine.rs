@@ -0,0 +1,182 @@
+use crate::disassemble::symbolize;
+use crate::dwarf_data::DwarfData;
+
+/// Stop printing a string after this many characters, like gdb's default "print elements"
+pub const MAX_STRING_LEN: usize = 200;
+
+/// x reads memory this many units (or instructions) at a time, so that a huge count doesn't need
+/// a huge buffer. It is a multiple of every `units_per_line`, so lines aren't split.
+pub const CHUNK_UNITS: usize = 512;
+
+/// A parsed x/FMT format: a repeat count, a format letter and a unit size, in any order after the
+/// count (e.g. "16xb", "4gx", "s").
+#[derive(Debug, Clone, Copy)]
+pub struct ExamineFormat {
+    pub count: usize,
+    pub format: char,
+    /// Unit size in bytes; unused by the s and i formats
+    pub size: usize,
+}
+
+impl ExamineFormat {
+    pub fn parse(spec: &str) -> Result<ExamineFormat, String> {
+        let count_len = spec.chars().take_while(|c| c.is_ascii_digit()).count();
+        let count = match count_len {
+            0 => 1,
+            _ => spec[..count_len]
+                .parse::<usize>()
+                .map_err(|e| e.to_string())?,
+        };
+        let mut format = None;
+        let mut size = None;
+        for c in spec[count_len..].chars() {
+            match c {
+                'b' => size = Some(1),
+                'h' => size = Some(2),
+                'w' => size = Some(4),
+                'g' => size = Some(8),
+                'x' | 'd' | 'u' | 'o' | 't' | 'c' | 'a' | 's' | 'i' => format = Some(c),
+                _ => return Err(format!("Invalid format letter '{}'.", c)),
+            }
+        }
+        let format = format.unwrap_or('x');
+        let size = match format {
+            'c' => size.unwrap_or(1),
+            'a' => 8,
+            _ => size.unwrap_or(4),
+        };
+        Ok(ExamineFormat {
+            count,
+            format,
+            size,
+        })
+    }
+
+    /// Number of units gdb prints on one line for this format.
+    fn units_per_line(&self) -> usize {
+        match (self.format, self.size) {
+            ('a', _) => 2,
+            (_, 8) => 2,
+            (_, 4) => 4,
+            _ => 8,
+        }
+    }
+
+    /// Formats one unit of memory. `bytes` must be exactly `size` bytes long.
+    fn format_unit(&self, debug_data: &DwarfData, bytes: &[u8]) -> String {
+        let mut raw = [0u8; 8];
+        raw[..bytes.len()].copy_from_slice(bytes);
+        let value = u64::from_le_bytes(raw);
+        let shift = 64 - 8 * self.size as u32;
+        let signed = ((value << shift) as i64) >> shift;
+        match self.format {
+            'd' => format!("{}", signed),
+            'u' => format!("{}", value),
+            'o' => format!("0{:o}", value),
+            't' => format!("{:0width$b}", value, width = self.size * 8),
+            'c' => format!("{} '{}'", signed, escape_char(bytes[0])),
+            'a' => {
+                let symbol = symbolize(debug_data, value as usize);
+                if symbol.is_empty() {
+                    format!("{:#x}", value)
+                } else {
+                    format!("{:#x} {}", value, symbol)
+                }
+            }
+            _ => format!("0x{:0width$x}", value, width = self.size * 2),
+        }
+    }
+}
+
+/// Escapes a byte the way it would appear in a C character or string literal.
+pub fn escape_char(byte: u8) -> String {
+    match byte {
+        b'\n' => "\\n".to_string(),
+        b'\t' => "\\t".to_string(),
+        b'\r' => "\\r".to_string(),
+        0 => "\\000".to_string(),
+        b'\\' => "\\\\".to_string(),
+        b'\'' => "\\'".to_string(),
+        b'"' => "\\\"".to_string(),
+        0x20..=0x7e => (byte as char).to_string(),
+        _ => format!("\\{:03o}", byte),
+    }
+}
+
+fn address_label(debug_data: &DwarfData, addr: usize) -> String {
+    let symbol = symbolize(debug_data, addr);
+    if symbol.is_empty() {
+        format!("{:#x}:", addr)
+    } else {
+        format!("{:#x} {}:", addr, symbol)
+    }
+}
+
+/// Prints `bytes`, which were read from `addr`, as units of the given format. Returns the number
+/// of bytes printed, which is less than requested if `bytes` was cut short.
+pub fn print_units(
+    debug_data: &DwarfData,
+    format: &ExamineFormat,
+    bytes: &[u8],
+    addr: usize,
+) -> usize {
+    let units = (bytes.len() / format.size).min(format.count);
+    for line in 0..(units + format.units_per_line() - 1) / format.units_per_line() {
+        let first = line * format.units_per_line();
+        let last = (first + format.units_per_line()).min(units);
+        let mut output = address_label(debug_data, addr + first * format.size);
+        for unit in first..last {
+            let start = unit * format.size;
+            output.push('\t');
+            output.push_str(&format.format_unit(debug_data, &bytes[start..start + format.size]));
+        }
+        println!("{}", output);
+    }
+    units * format.size
+}
+
+/// Prints the NUL-terminated string at the start of `bytes`, which were read from `addr`.
+/// Returns the number of bytes consumed (including the NUL), or None if the string runs past the
+/// end of `bytes` without getting to MAX_STRING_LEN.
+pub fn print_string(debug_data: &DwarfData, bytes: &[u8], addr: usize) -> Option<usize> {
+    let (len, consumed, truncated) = match bytes.iter().position(|b| *b == 0) {
+        Some(nul) if nul <= MAX_STRING_LEN => (nul, nul + 1, false),
+        _ if bytes.len() >= MAX_STRING_LEN => (MAX_STRING_LEN, MAX_STRING_LEN, true),
+        _ => return None,
+    };
+    let text: String = bytes[..len].iter().map(|b| escape_char(*b)).collect();
+    println!(
+        "{}\t\"{}\"{}",
+        address_label(debug_data, addr),
+        text,
+        if truncated { "..." } else { "" }
+    );
+    Some(consumed)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(spec: &str) -> (usize, char, usize) {
+        let format = ExamineFormat::parse(spec).unwrap();
+        (format.count, format.format, format.size)
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(""), (1, 'x', 4));
+        assert_eq!(parse("16xb"), (16, 'x', 1));
+        assert_eq!(parse("4gx"), (4, 'x', 8));
+        assert_eq!(parse("2dh"), (2, 'd', 2));
+        assert_eq!(parse("w"), (1, 'x', 4));
+        assert_eq!(parse("3c"), (3, 'c', 1));
+        assert_eq!(parse("ab"), (1, 'a', 8));
+        assert_eq!(parse("s"), (1, 's', 4));
+        assert_eq!(parse("10i"), (10, 'i', 4));
+        assert_eq!(parse("99999999999g"), (99_999_999_999, 'x', 8));
+        assert!(ExamineFormat::parse("2z").is_err());
+        assert!(ExamineFormat::parse("4x2").is_err());
+        assert!(ExamineFormat::parse("99999999999999999999999g").is_err());
+    }
+}
@@ -0,0 +1,929 @@
+use crate::dwarf_data::{DwarfData, Type, TypeKind};
+use crate::registers;
+use std::mem::size_of;
+
+/// Operators and punctuation, longest first so that e.g. "<=" isn't read as "<" then "="
+const PUNCTUATION: [&str; 27] = [
+    "->", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "+", "-", "*", "/", "%", "<", ">", "&",
+    "|", "^", "!", "~", "(", ")", "[", "]", ".", "=",
+];
+
+/// Binary operators by precedence, loosest first
+const BINARY_OPERATORS: [&[&str]; 10] = [
+    &["||"],
+    &["&&"],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["==", "!="],
+    &["<", ">", "<=", ">="],
+    &["<<", ">>"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// Words that can start a type name in a cast
+const TYPE_KEYWORDS: [&str; 15] = [
+    "struct", "union", "enum", "unsigned", "signed", "const", "volatile", "void", "char", "short",
+    "int", "long", "float", "double", "_Bool",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Int(u64),
+    Float(f64),
+    Char(u8),
+    Ident(String),
+    Register(String),
+    Punct(&'static str),
+}
+
+/// A parsed C expression.
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Int(u64),
+    Float(f64),
+    Char(u8),
+    Variable(String),
+    Register(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    /// a.b (a->b is parsed as (*a).b)
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    /// A cast to the named type, e.g. "struct node *"
+    Cast(String, Box<Expr>),
+}
+
+/// The result of evaluating an expression: the raw bytes of a value of type `ty`, and where it
+/// lives in memory, if anywhere.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub ty: Type,
+    pub bytes: Vec<u8>,
+    pub address: Option<usize>,
+}
+
+/// What expressions are evaluated against: the variables, registers and memory of the program.
+pub trait Environment {
+    fn debug_data(&self) -> &DwarfData;
+    /// Reads the variable called `name`. Returns None if there is no such variable.
+    fn variable(&self, name: &str) -> Option<Result<Value, String>>;
+    fn register(&self, name: &str) -> Result<u64, String>;
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>>;
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c.is_ascii_digit()
+            || (c == '.' && chars.get(i + 1).map_or(false, |c| c.is_ascii_digit()))
+        {
+            let start = i;
+            while i < chars.len() {
+                let exponent_sign = (chars[i] == '+' || chars[i] == '-')
+                    && matches!(chars[i - 1], 'e' | 'E')
+                    && !chars[start..i].iter().any(|c| *c == 'x' || *c == 'X');
+                if !(chars[i].is_ascii_alphanumeric() || chars[i] == '.' || exponent_sign) {
+                    break;
+                }
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            tokens.push(parse_number(&text)?);
+        } else if c.is_ascii_alphabetic() || c == '_' || c == '$' {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            if c == '$' {
+                tokens.push(Token::Register(text[1..].to_string()));
+            } else {
+                tokens.push(Token::Ident(text));
+            }
+        } else if c == '\'' {
+            let (byte, len) = parse_char_literal(&chars[i + 1..])?;
+            tokens.push(Token::Char(byte));
+            i += len + 1;
+        } else {
+            let rest: String = chars[i..].iter().take(2).collect();
+            match PUNCTUATION.iter().find(|punct| rest.starts_with(*punct)) {
+                Some(punct) => {
+                    tokens.push(Token::Punct(punct));
+                    i += punct.len();
+                }
+                None => return Err(format!("Invalid character '{}' in expression.", c)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses an integer (decimal, 0x hex or 0 octal) or floating point literal.
+fn parse_number(text: &str) -> Result<Token, String> {
+    let lower = text.to_lowercase();
+    let invalid = || format!("Invalid number \"{}\".", text);
+    if lower.starts_with("0x") {
+        let digits = lower[2..].trim_end_matches(&['u', 'l'][..]);
+        return u64::from_str_radix(digits, 16)
+            .map(Token::Int)
+            .map_err(|_| invalid());
+    }
+    if lower.contains('.') || lower.contains('e') {
+        let digits = lower.trim_end_matches(&['f', 'l'][..]);
+        return digits
+            .parse::<f64>()
+            .map(Token::Float)
+            .map_err(|_| invalid());
+    }
+    let digits = lower.trim_end_matches(&['u', 'l'][..]);
+    let radix = if digits.len() > 1 && digits.starts_with('0') {
+        8
+    } else {
+        10
+    };
+    u64::from_str_radix(digits, radix)
+        .map(Token::Int)
+        .map_err(|_| invalid())
+}
+
+/// Parses the rest of a character literal (after the opening quote). Returns the character and
+/// the number of chars consumed, including the closing quote.
+fn parse_char_literal(chars: &[char]) -> Result<(u8, usize), String> {
+    let unterminated = || "Unmatched single quote.".to_string();
+    let (byte, len) = match chars.first() {
+        Some('\\') => match chars.get(1) {
+            Some('n') => (b'\n', 2),
+            Some('t') => (b'\t', 2),
+            Some('r') => (b'\r', 2),
+            Some('\\') => (b'\\', 2),
+            Some('\'') => (b'\'', 2),
+            Some('"') => (b'"', 2),
+            Some(c) if c.is_digit(8) => {
+                let digits: String = chars[1..]
+                    .iter()
+                    .take(3)
+                    .take_while(|c| c.is_digit(8))
+                    .collect();
+                let value = u32::from_str_radix(&digits, 8).map_err(|e| e.to_string())?;
+                (value as u8, digits.len() + 1)
+            }
+            _ => return Err(unterminated()),
+        },
+        Some(c) if c.is_ascii() && *c != '\'' => (*c as u8, 1),
+        _ => return Err(unterminated()),
+    };
+    match chars.get(len) {
+        Some('\'') => Ok((byte, len + 1)),
+        _ => Err(unterminated()),
+    }
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    debug_data: &'a DwarfData,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_punct(&self) -> Option<&'static str> {
+        match self.peek() {
+            Some(Token::Punct(punct)) => Some(punct),
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, punct: &str) -> Result<(), String> {
+        if self.peek_punct() == Some(punct) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err("A syntax error in expression.".to_string())
+        }
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Expr, String> {
+        if level == BINARY_OPERATORS.len() {
+            return self.parse_unary();
+        }
+        let mut lhs = self.parse_binary(level + 1)?;
+        while let Some(op) = self.peek_punct() {
+            if !BINARY_OPERATORS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.parse_binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        match self.peek_punct() {
+            Some(op) if ["-", "+", "!", "~", "*", "&"].contains(&op) => {
+                self.pos += 1;
+                Ok(Expr::Unary(op, Box::new(self.parse_unary()?)))
+            }
+            Some("(") if self.is_cast() => {
+                self.pos += 1;
+                let mut words = Vec::new();
+                while let Some(Token::Ident(word)) = self.peek() {
+                    words.push(word.clone());
+                    self.pos += 1;
+                }
+                let mut stars = String::new();
+                while self.peek_punct() == Some("*") {
+                    stars.push('*');
+                    self.pos += 1;
+                }
+                self.expect(")")?;
+                let name = if stars.is_empty() {
+                    words.join(" ")
+                } else {
+                    format!("{} {}", words.join(" "), stars)
+                };
+                Ok(Expr::Cast(name, Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_postfix(),
+        }
+    }
+
+    /// Returns true if the "(" at the current position starts a cast rather than a
+    /// parenthesized expression.
+    fn is_cast(&self) -> bool {
+        match self.tokens.get(self.pos + 1) {
+            Some(Token::Ident(word)) => {
+                TYPE_KEYWORDS.contains(&word.as_str())
+                    || self
+                        .debug_data
+                        .get_type_by_name(word)
+                        .map_or(false, |ty| matches!(ty.kind, TypeKind::Alias(_)))
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_postfix(&mut self) -> Result<Expr, String> {
+        let mut expr = self.parse_primary()?;
+        loop {
+            match self.peek_punct() {
+                Some("[") => {
+                    self.pos += 1;
+                    let index = self.parse_binary(0)?;
+                    self.expect("]")?;
+                    expr = Expr::Index(Box::new(expr), Box::new(index));
+                }
+                Some(op) if op == "." || op == "->" => {
+                    self.pos += 1;
+                    let member = match self.peek() {
+                        Some(Token::Ident(member)) => member.clone(),
+                        _ => return Err("A syntax error in expression.".to_string()),
+                    };
+                    self.pos += 1;
+                    if op == "->" {
+                        expr = Expr::Unary("*", Box::new(expr));
+                    }
+                    expr = Expr::Member(Box::new(expr), member);
+                }
+                _ => return Ok(expr),
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or("A syntax error in expression.".to_string())?;
+        self.pos += 1;
+        match token {
+            Token::Int(value) => Ok(Expr::Int(value)),
+            Token::Float(value) => Ok(Expr::Float(value)),
+            Token::Char(value) => Ok(Expr::Char(value)),
+            Token::Ident(name) => Ok(Expr::Variable(name)),
+            Token::Register(name) => Ok(Expr::Register(name)),
+            Token::Punct("(") => {
+                let expr = self.parse_binary(0)?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Punct(_) => Err("A syntax error in expression.".to_string()),
+        }
+    }
+}
+
+/// Parses a C expression. `debug_data` is needed to tell casts to typedefs from parenthesized
+/// variables.
+pub fn parse(input: &str, debug_data: &DwarfData) -> Result<Expr, String> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+        debug_data,
+    };
+    if parser.tokens.is_empty() {
+        return Err("Argument required (expression to compute).".to_string());
+    }
+    let expr = parser.parse_binary(0)?;
+    if parser.pos < parser.tokens.len() {
+        return Err("A syntax error in expression.".to_string());
+    }
+    Ok(expr)
+}
+
+/// Parses and evaluates a C expression.
+pub fn evaluate(input: &str, env: &dyn Environment) -> Result<Value, String> {
+    evaluate_expr(&parse(input, env.debug_data())?, env)
+}
+
+impl Value {
+    fn integer(value: i64, type_name: &str) -> Value {
+        let ty = Type::from_c_name(type_name).unwrap();
+        Value {
+            bytes: ty.encode_integer(value),
+            ty,
+            address: None,
+        }
+    }
+
+    fn float(value: f64) -> Value {
+        let ty = Type::from_c_name("double").unwrap();
+        Value {
+            bytes: ty.encode_float(value).unwrap(),
+            ty,
+            address: None,
+        }
+    }
+
+    /// Returns the value as an integer, if it's an integer, enum or pointer.
+    pub fn as_integer(&self, debug_data: &DwarfData) -> Option<i64> {
+        debug_data
+            .strip_aliases(&self.ty)?
+            .integer_value(&self.bytes)
+    }
+
+    /// Returns the value as a float; integers are converted.
+    pub fn as_float(&self, debug_data: &DwarfData) -> Option<f64> {
+        let ty = debug_data.strip_aliases(&self.ty)?;
+        if ty.is_float() {
+            ty.float_value(&self.bytes)
+        } else if self.is_unsigned(debug_data) {
+            Some(self.as_integer(debug_data)? as u64 as f64)
+        } else {
+            Some(self.as_integer(debug_data)? as f64)
+        }
+    }
+
+    fn is_float(&self, debug_data: &DwarfData) -> bool {
+        debug_data
+            .strip_aliases(&self.ty)
+            .map_or(false, |ty| ty.is_float())
+    }
+
+    fn is_pointer(&self, debug_data: &DwarfData) -> bool {
+        debug_data
+            .strip_aliases(&self.ty)
+            .map_or(false, |ty| matches!(ty.kind, TypeKind::Pointer(_)))
+    }
+
+    fn is_unsigned(&self, debug_data: &DwarfData) -> bool {
+        debug_data.strip_aliases(&self.ty).map_or(false, |ty| {
+            ty.name.contains("unsigned") || matches!(ty.kind, TypeKind::Pointer(_))
+        })
+    }
+
+    /// Returns true if the value is non-zero, as in a C condition.
+    pub fn is_true(&self, debug_data: &DwarfData) -> Result<bool, String> {
+        match self.as_float(debug_data) {
+            Some(value) => Ok(value != 0.0),
+            None => Err(format!(
+                "Cannot convert value of type {} to a truth value.",
+                self.ty.name
+            )),
+        }
+    }
+}
+
+fn evaluate_expr(expr: &Expr, env: &dyn Environment) -> Result<Value, String> {
+    let debug_data = env.debug_data();
+    match expr {
+        Expr::Int(value) => {
+            let type_name = if *value <= i32::MAX as u64 {
+                "int"
+            } else if *value <= i64::MAX as u64 {
+                "long"
+            } else {
+                "unsigned long"
+            };
+            Ok(Value::integer(*value as i64, type_name))
+        }
+        Expr::Float(value) => Ok(Value::float(*value)),
+        Expr::Char(value) => Ok(Value::integer(*value as i64, "char")),
+        Expr::Variable(name) => match env.variable(name) {
+            Some(value) => value,
+            None if debug_data.get_enumerator(name).is_some() => {
+                let (ty, value) = debug_data.get_enumerator(name).unwrap();
+                Ok(Value {
+                    bytes: ty.encode_integer(value),
+                    ty: ty.clone(),
+                    address: None,
+                })
+            }
+            None => match debug_data.get_addr_for_function(None, name) {
+                // functions evaluate to their address
+                Some(addr) => Ok(Value {
+                    ty: Type {
+                        name: "void (*)(void)".to_string(),
+                        size: size_of::<usize>(),
+                        kind: TypeKind::Pointer(None),
+                    },
+                    bytes: addr.to_le_bytes().to_vec(),
+                    address: None,
+                }),
+                None => Err(format!("No symbol \"{}\" in current context.", name)),
+            },
+        },
+        Expr::Register(name) => {
+            let value = env.register(name)?;
+            let type_name = if registers::is_pointer_register(name) {
+                "void *"
+            } else {
+                "long"
+            };
+            Ok(Value::integer(value as i64, type_name))
+        }
+        Expr::Unary(op, operand) => {
+            let operand = evaluate_expr(operand, env)?;
+            evaluate_unary(op, operand, env)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate_expr(lhs, env)?;
+            // && and || don't evaluate their right side if they don't need to, so that e.g.
+            // "p && p->x" is safe
+            if *op == "&&" || *op == "||" {
+                let lhs = lhs.is_true(debug_data)?;
+                let result = if lhs == (*op == "||") {
+                    lhs
+                } else {
+                    evaluate_expr(rhs, env)?.is_true(debug_data)?
+                };
+                return Ok(Value::integer(result as i64, "int"));
+            }
+            let rhs = evaluate_expr(rhs, env)?;
+            evaluate_binary(op, decay(lhs, env)?, decay(rhs, env)?, env)
+        }
+        Expr::Member(base, name) => {
+            let base = evaluate_expr(base, env)?;
+            let (member, member_type) = debug_data.get_member(&base.ty, name).ok_or_else(|| {
+                match debug_data.strip_aliases(&base.ty).map(|ty| &ty.kind) {
+                    Some(TypeKind::Struct(_)) | Some(TypeKind::Union(_)) => {
+                        format!("There is no member named {}.", name)
+                    }
+                    _ => "Attempt to extract a component of a value that is not a structure."
+                        .to_string(),
+                }
+            })?;
+            Ok(Value {
+                bytes: debug_data.get_member_bytes(&member, member_type, &base.bytes),
+                ty: member_type.clone(),
+                address: match member.bits {
+                    Some(_) => None,
+                    None => base.address.map(|addr| addr + member.offset),
+                },
+            })
+        }
+        Expr::Index(base, index) => {
+            let base = evaluate_expr(base, env)?;
+            let index = evaluate_expr(index, env)?;
+            let index = index
+                .as_integer(debug_data)
+                .ok_or("Array subscript is not an integer.".to_string())?;
+            let element = debug_data.get_element_type(&base.ty).ok_or(format!(
+                "cannot subscript something of type `{}'",
+                base.ty.name
+            ))?;
+            let offset = index.wrapping_mul(element.size as i64);
+            if !base.is_pointer(debug_data) && base.address.is_none() {
+                // an array that isn't in memory, e.g. one returned by a register
+                let start = offset as usize;
+                let bytes = start
+                    .checked_add(element.size)
+                    .and_then(|end| base.bytes.get(start..end))
+                    .ok_or("no such vector element".to_string())?;
+                return Ok(Value {
+                    bytes: bytes.to_vec(),
+                    ty: element,
+                    address: None,
+                });
+            }
+            let start = match base.address {
+                Some(addr) if !base.is_pointer(debug_data) => addr,
+                _ => base.as_integer(debug_data).unwrap_or(0) as usize,
+            };
+            read_value(element, start.wrapping_add(offset as usize), env)
+        }
+        Expr::Cast(type_name, operand) => {
+            let operand = decay(evaluate_expr(operand, env)?, env)?;
+            let target = resolve_type(type_name, debug_data)?;
+            cast(operand, target, env)
+        }
+    }
+}
+
+fn evaluate_unary(op: &str, operand: Value, env: &dyn Environment) -> Result<Value, String> {
+    let debug_data = env.debug_data();
+    match op {
+        "&" => {
+            let addr = operand
+                .address
+                .ok_or("Attempt to take address of value not located in memory.".to_string())?;
+            let ty = debug_data.pointer_to(&operand.ty);
+            Ok(Value {
+                bytes: ty.encode_integer(addr as i64),
+                ty,
+                address: None,
+            })
+        }
+        "*" => {
+            let operand = decay(operand, env)?;
+            if !operand.is_pointer(debug_data) {
+                // like gdb, an integer is taken as the address of an int
+                return match operand.as_integer(debug_data) {
+                    Some(addr) if !operand.is_float(debug_data) => {
+                        read_value(Type::from_c_name("int").unwrap(), addr as usize, env)
+                    }
+                    _ => Err("Attempt to take contents of a non-pointer value.".to_string()),
+                };
+            }
+            let pointee = debug_data
+                .get_pointee(&operand.ty)
+                .and_then(|pointee| debug_data.strip_aliases(pointee).map(|_| pointee))
+                .ok_or("Attempt to take contents of a non-pointer value.".to_string())?;
+            let addr = operand.as_integer(debug_data).unwrap_or(0) as usize;
+            read_value(pointee.clone(), addr, env)
+        }
+        "!" => Ok(Value::integer(!operand.is_true(debug_data)? as i64, "int")),
+        _ if operand.is_float(debug_data) => {
+            let value = operand.as_float(debug_data).unwrap();
+            match op {
+                "-" => Ok(Value::float(-value)),
+                "+" => Ok(Value::float(value)),
+                _ => Err("Argument to complement operation not an integer, boolean.".to_string()),
+            }
+        }
+        _ => {
+            let value = operand
+                .as_integer(debug_data)
+                .ok_or("Argument to arithmetic operation not a number or boolean.".to_string())?;
+            let type_name = integer_type_name(&operand, &operand, debug_data);
+            let result = match op {
+                "-" => value.wrapping_neg(),
+                "~" => !value,
+                _ => value,
+            };
+            Ok(Value::integer(result, type_name))
+        }
+    }
+}
+
+fn evaluate_binary(
+    op: &str,
+    lhs: Value,
+    rhs: Value,
+    env: &dyn Environment,
+) -> Result<Value, String> {
+    let debug_data = env.debug_data();
+    let not_a_number = || "Argument to arithmetic operation not a number or boolean.".to_string();
+    // pointer arithmetic is scaled by the size of what the pointer points to
+    let pointee_size = |value: &Value| {
+        debug_data
+            .get_pointee(&value.ty)
+            .and_then(|pointee| debug_data.strip_aliases(pointee))
+            .map_or(1, |pointee| pointee.size.max(1)) as i64
+    };
+    match (op, lhs.is_pointer(debug_data), rhs.is_pointer(debug_data)) {
+        ("+", true, false) | ("-", true, false) => {
+            let offset = rhs.as_integer(debug_data).ok_or_else(not_a_number)?;
+            let offset = offset.wrapping_mul(pointee_size(&lhs));
+            let addr = lhs.as_integer(debug_data).unwrap();
+            let result = if op == "+" {
+                addr.wrapping_add(offset)
+            } else {
+                addr.wrapping_sub(offset)
+            };
+            return Ok(Value {
+                bytes: lhs.ty.encode_integer(result),
+                ty: lhs.ty,
+                address: None,
+            });
+        }
+        ("+", false, true) => return evaluate_binary(op, rhs, lhs, env),
+        ("-", true, true) => {
+            let diff = lhs
+                .as_integer(debug_data)
+                .unwrap()
+                .wrapping_sub(rhs.as_integer(debug_data).unwrap());
+            return Ok(Value::integer(diff / pointee_size(&lhs), "long"));
+        }
+        _ => {}
+    }
+    let is_comparison = ["==", "!=", "<", ">", "<=", ">="].contains(&op);
+    if lhs.is_float(debug_data) || rhs.is_float(debug_data) {
+        let lhs = lhs.as_float(debug_data).ok_or_else(not_a_number)?;
+        let rhs = rhs.as_float(debug_data).ok_or_else(not_a_number)?;
+        let result = match op {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" => lhs / rhs,
+            _ if is_comparison => return Ok(Value::integer(compare(op, lhs, rhs) as i64, "int")),
+            _ => return Err("Integer only operation.".to_string()),
+        };
+        return Ok(Value::float(result));
+    }
+    let type_name = integer_type_name(&lhs, &rhs, debug_data);
+    let unsigned = type_name.starts_with("unsigned");
+    let lhs = lhs.as_integer(debug_data).ok_or_else(not_a_number)?;
+    let rhs = rhs.as_integer(debug_data).ok_or_else(not_a_number)?;
+    if is_comparison {
+        let result = if unsigned {
+            compare(op, lhs as u64, rhs as u64)
+        } else {
+            compare(op, lhs, rhs)
+        };
+        return Ok(Value::integer(result as i64, "int"));
+    }
+    if (op == "/" || op == "%") && rhs == 0 {
+        return Err("Division by zero".to_string());
+    }
+    let result = match op {
+        "+" => lhs.wrapping_add(rhs),
+        "-" => lhs.wrapping_sub(rhs),
+        "*" => lhs.wrapping_mul(rhs),
+        "/" if unsigned => ((lhs as u64) / (rhs as u64)) as i64,
+        "/" => lhs.wrapping_div(rhs),
+        "%" if unsigned => ((lhs as u64) % (rhs as u64)) as i64,
+        "%" => lhs.wrapping_rem(rhs),
+        "<<" => lhs.wrapping_shl(rhs as u32),
+        ">>" if unsigned => ((lhs as u64).wrapping_shr(rhs as u32)) as i64,
+        ">>" => lhs.wrapping_shr(rhs as u32),
+        "&" => lhs & rhs,
+        "|" => lhs | rhs,
+        _ => lhs ^ rhs,
+    };
+    Ok(Value::integer(result, type_name))
+}
+
+fn compare<T: PartialOrd>(op: &str, lhs: T, rhs: T) -> bool {
+    match op {
+        "==" => lhs == rhs,
+        "!=" => lhs != rhs,
+        "<" => lhs < rhs,
+        ">" => lhs > rhs,
+        "<=" => lhs <= rhs,
+        _ => lhs >= rhs,
+    }
+}
+
+/// The type C's usual arithmetic conversions give the result of an operation on two integers.
+fn integer_type_name(lhs: &Value, rhs: &Value, debug_data: &DwarfData) -> &'static str {
+    let size = lhs.ty.size.max(rhs.ty.size);
+    let unsigned = (lhs.ty.size == size && lhs.is_unsigned(debug_data))
+        || (rhs.ty.size == size && rhs.is_unsigned(debug_data));
+    match (size > 4, unsigned && size >= 4) {
+        (false, false) => "int",
+        (false, true) => "unsigned int",
+        (true, false) => "long",
+        (true, true) => "unsigned long",
+    }
+}
+
+/// Turns an array into a pointer to its first element, as C does when arrays are used in
+/// arithmetic.
+fn decay(value: Value, env: &dyn Environment) -> Result<Value, String> {
+    let debug_data = env.debug_data();
+    match debug_data.strip_aliases(&value.ty).map(|ty| &ty.kind) {
+        Some(TypeKind::Array(_, _)) => {
+            let element = debug_data.get_element_type(&value.ty).unwrap();
+            let addr = value
+                .address
+                .ok_or("Attempt to take address of value not located in memory.".to_string())?;
+            let ty = debug_data.pointer_to(&element);
+            Ok(Value {
+                bytes: ty.encode_integer(addr as i64),
+                ty,
+                address: None,
+            })
+        }
+        _ => Ok(value),
+    }
+}
+
+/// Reads a value of type `ty` from memory.
+fn read_value(ty: Type, addr: usize, env: &dyn Environment) -> Result<Value, String> {
+    let bytes = env
+        .read_memory(addr, ty.size)
+        .filter(|bytes| bytes.len() == ty.size)
+        .ok_or(format!("Cannot access memory at address {:#x}", addr))?;
+    Ok(Value {
+        ty,
+        bytes,
+        address: Some(addr),
+    })
+}
+
+/// Finds the type named in a cast, e.g. "int", "struct node *" or "unsigned char **".
+fn resolve_type(name: &str, debug_data: &DwarfData) -> Result<Type, String> {
+    let base = name.trim_end_matches(&['*', ' '][..]);
+    let mut ty = match debug_data.get_type_by_name(base) {
+        Some(ty) => ty.clone(),
+        None => {
+            Type::from_c_name(base).ok_or(format!("No symbol \"{}\" in current context.", base))?
+        }
+    };
+    for _ in 0..name.matches('*').count() {
+        ty = match debug_data.get_type_by_name(&debug_data.pointer_to(&ty).name) {
+            Some(pointer) => pointer.clone(),
+            None => debug_data.pointer_to(&ty),
+        };
+    }
+    Ok(ty)
+}
+
+fn cast(value: Value, target: Type, env: &dyn Environment) -> Result<Value, String> {
+    let debug_data = env.debug_data();
+    let stripped = match debug_data.strip_aliases(&target) {
+        Some(stripped) if stripped.is_scalar() => stripped,
+        _ => return Err("Invalid cast.".to_string()),
+    };
+    let bytes = if stripped.is_float() {
+        let value = value
+            .as_float(debug_data)
+            .ok_or("Invalid cast.".to_string())?;
+        stripped
+            .encode_float(value)
+            .ok_or("Invalid cast.".to_string())?
+    } else if value.is_float(debug_data) {
+        stripped.encode_integer(value.as_float(debug_data).unwrap() as i64)
+    } else {
+        let value = value
+            .as_integer(debug_data)
+            .ok_or("Invalid cast.".to_string())?;
+        stripped.encode_integer(value)
+    };
+    Ok(Value {
+        ty: target,
+        bytes,
+        address: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Evaluates expressions without a program: there are no variables or memory, and only the
+    /// types of a sample binary.
+    struct TestEnv {
+        debug_data: DwarfData,
+    }
+
+    impl Environment for TestEnv {
+        fn debug_data(&self) -> &DwarfData {
+            &self.debug_data
+        }
+
+        fn variable(&self, _name: &str) -> Option<Result<Value, String>> {
+            None
+        }
+
+        fn register(&self, name: &str) -> Result<u64, String> {
+            match name {
+                "rax" => Ok(42),
+                _ => Err(format!("Invalid register `{}'", name)),
+            }
+        }
+
+        fn read_memory(&self, _addr: usize, _len: usize) -> Option<Vec<u8>> {
+            None
+        }
+    }
+
+    /// The types come from samples/hello, which `make` builds. Without it the tests that need
+    /// types are skipped, so a fresh checkout still passes.
+    fn test_env() -> Option<TestEnv> {
+        match DwarfData::from_file("samples/hello") {
+            Ok(debug_data) => Some(TestEnv { debug_data }),
+            Err(_) => {
+                eprintln!("Skipping: could not load samples/hello. Run make to build it.");
+                None
+            }
+        }
+    }
+
+    fn evaluate_integer(env: &TestEnv, input: &str) -> i64 {
+        let value = evaluate(input, env).unwrap();
+        value.as_integer(&env.debug_data).unwrap()
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("p->next <= 0x1f").unwrap(),
+            vec![
+                Token::Ident("p".to_string()),
+                Token::Punct("->"),
+                Token::Ident("next".to_string()),
+                Token::Punct("<="),
+                Token::Int(31),
+            ]
+        );
+        assert_eq!(
+            tokenize("$rip+1.5e-3").unwrap(),
+            vec![
+                Token::Register("rip".to_string()),
+                Token::Punct("+"),
+                Token::Float(1.5e-3),
+            ]
+        );
+        assert!(tokenize("a @ b").is_err());
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("42"), Ok(Token::Int(42)));
+        assert_eq!(parse_number("0x1F"), Ok(Token::Int(31)));
+        assert_eq!(parse_number("010"), Ok(Token::Int(8)));
+        assert_eq!(parse_number("0"), Ok(Token::Int(0)));
+        assert_eq!(parse_number("10UL"), Ok(Token::Int(10)));
+        assert_eq!(parse_number("0x1e"), Ok(Token::Int(30)));
+        assert_eq!(parse_number("2.5f"), Ok(Token::Float(2.5)));
+        assert_eq!(parse_number("1e3"), Ok(Token::Float(1000.0)));
+        assert!(parse_number("09").is_err());
+        assert!(parse_number("0xg").is_err());
+    }
+
+    #[test]
+    fn test_char_literals() {
+        assert_eq!(tokenize("'a'").unwrap(), vec![Token::Char(b'a')]);
+        assert_eq!(tokenize("'\\n'").unwrap(), vec![Token::Char(b'\n')]);
+        assert_eq!(tokenize("'\\0'").unwrap(), vec![Token::Char(0)]);
+        assert_eq!(tokenize("'\\101'").unwrap(), vec![Token::Char(b'A')]);
+        assert_eq!(tokenize("'\\''").unwrap(), vec![Token::Char(b'\'')]);
+        assert_eq!(tokenize("' '").unwrap(), vec![Token::Char(b' ')]);
+        assert!(tokenize("'a").is_err());
+        assert!(tokenize("'ab'").is_err());
+    }
+
+    #[test]
+    fn test_precedence() {
+        let env = match test_env() {
+            Some(env) => env,
+            None => return,
+        };
+        assert_eq!(evaluate_integer(&env, "1 + 2 * 3"), 7);
+        assert_eq!(evaluate_integer(&env, "(1 + 2) * 3"), 9);
+        assert_eq!(evaluate_integer(&env, "7 - 2 - 1"), 4);
+        assert_eq!(evaluate_integer(&env, "1 << 2 + 1"), 8);
+        assert_eq!(evaluate_integer(&env, "1 < 2 == 1"), 1);
+        assert_eq!(evaluate_integer(&env, "6 & 3 | 8 ^ 1"), 11);
+        assert_eq!(evaluate_integer(&env, "1 || 0 && 0"), 1);
+        assert_eq!(evaluate_integer(&env, "-2 * -3"), 6);
+        assert_eq!(evaluate_integer(&env, "!0 + ~0"), 0);
+        assert_eq!(evaluate_integer(&env, "$rax % 5"), 2);
+        assert_eq!(evaluate_integer(&env, "'a' + 1"), 98);
+        assert!(evaluate("1 / 0", &env).is_err());
+        assert!(evaluate("(1 + 2", &env).is_err());
+    }
+
+    #[test]
+    fn test_casts() {
+        let env = match test_env() {
+            Some(env) => env,
+            None => return,
+        };
+        assert_eq!(evaluate_integer(&env, "(char)321"), 65);
+        assert_eq!(evaluate_integer(&env, "(unsigned char)-1"), 255);
+        assert_eq!(evaluate_integer(&env, "(short)70000"), 4464);
+        assert_eq!(evaluate_integer(&env, "(int)3.9"), 3);
+        assert_eq!(evaluate_integer(&env, "(unsigned int)-1 > 0"), 1);
+        assert_eq!(evaluate_integer(&env, "(int *)0x10 + 1"), 0x14);
+        assert_eq!(evaluate_integer(&env, "(int *)0x20 - (int *)0x10"), 4);
+        assert_eq!(
+            evaluate_integer(&env, "(char *)0x8000000000000000 - (char *)1"),
+            i64::MAX
+        );
+        let half = evaluate("(double)1 / 2", &env).unwrap();
+        assert_eq!(half.as_float(&env.debug_data), Some(0.5));
+    }
+}
@@ -10,13 +10,21 @@ use gimli::{UnitOffset, UnitSectionOffset};
 use object::Object;
 use std::borrow;
 //use std::io::{BufWriter, Write};
-use crate::dwarf_data::{File, Function, Line, Location, Type, Variable};
+use crate::dwarf_data::{
+    File, FrameState, Function, Line, Location, Member, Place, Type, TypeKind, Variable,
+    MAX_TYPE_DEPTH,
+};
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::fmt::Write;
 use std::{io, path};
 
-pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<Vec<File>, Error> {
+/// Loads the compilation units of an executable, along with a table of all its types (by the
+/// .debug_info offset of their DIEs).
+pub fn load_file(
+    object: &object::File,
+    endian: gimli::RunTimeEndian,
+) -> Result<(Vec<File>, HashMap<usize, Type>), Error> {
     // Load a section and return as `Cow<[u8]>`.
     let load_section = |id: gimli::SectionId| -> Result<borrow::Cow<[u8]>, gimli::Error> {
         Ok(object
@@ -40,7 +48,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     let dwarf = dwarf_cow.borrow(&borrow_section);
 
     // Define a mapping from type offsets to type structs
-    let mut offset_to_type: HashMap<usize, Type> = HashMap::new();
+    let mut types: HashMap<usize, Type> = HashMap::new();
 
     let mut compilation_units: Vec<File> = Vec::new();
 
@@ -49,12 +57,18 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
     while let Some(header) = iter.next()? {
         let unit = dwarf.unit(header)?;
 
+        // Types go first, since variables (and other types) may refer to types defined later
+        load_types(&unit, &dwarf, &mut types)?;
+
         // Iterate over the Debugging Information Entries (DIEs) in the unit.
         let mut depth = 0;
+        // the function and lexical block enclosing each level of the tree
+        let mut scopes: Vec<Scope> = Vec::new();
         let mut entries = unit.entries();
         while let Some((delta_depth, entry)) = entries.next_dfs()? {
             depth += delta_depth;
-            // Update the offset_to_type mapping for types
+            scopes.truncate(depth as usize);
+            let scope = scopes.last().cloned().unwrap_or_default();
             // Update the variable list for formal params/variables
             match entry.tag() {
                 gimli::DW_TAG_compile_unit => {
@@ -72,35 +86,9 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         global_variables: Vec::new(),
                         functions: Vec::new(),
                         lines: Vec::new(),
+                        inlined_functions: Vec::new(),
                     });
                 }
-                gimli::DW_TAG_base_type => {
-                    let name = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_name) {
-                        if let Ok(DebugValue::Str(name)) = get_attr_value(&attr, &unit, &dwarf) {
-                            name
-                        } else {
-                            "<unknown>".to_string()
-                        }
-                    } else {
-                        "<unknown>".to_string()
-                    };
-                    let byte_size = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_byte_size) {
-                        if let Ok(DebugValue::Uint(byte_size)) =
-                            get_attr_value(&attr, &unit, &dwarf)
-                        {
-                            byte_size
-                        } else {
-                            // TODO: report error?
-                            0
-                        }
-                    } else {
-                        // TODO: report error?
-                        0
-                    };
-                    let type_offset = entry.offset().0;
-                    offset_to_type
-                        .insert(type_offset, Type::new(name, byte_size.try_into().unwrap()));
-                }
                 gimli::DW_TAG_subprogram => {
                     let mut func: Function = Default::default();
                     let mut attrs = entry.attrs();
@@ -132,65 +120,72 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             _ => {}
                         }
                     }
-                    compilation_units.last_mut().unwrap().functions.push(func);
+                    // out-of-line copies of inlined functions and the like only refer to the
+                    // DIE with the name
+                    if let Some(origin) = get_origin(entry, &unit) {
+                        if func.name.is_empty() {
+                            func.name = get_attr_str(&origin, gimli::DW_AT_name, &unit, &dwarf)
+                                .unwrap_or_default();
+                        }
+                        if func.line_number == 0 {
+                            func.line_number = get_attr_const(&origin, gimli::DW_AT_decl_line, &unit)
+                                .unwrap_or(0) as usize;
+                        }
+                    }
+                    // with -O2, functions may be split into hot and cold parts
+                    if func.address == 0 {
+                        if let Some(&(start, end)) = get_ranges(entry, &unit, &dwarf).first() {
+                            func.address = start;
+                            func.text_length = end - start;
+                        }
+                    }
+                    // abstract instances and declarations have no code
+                    if func.address == 0 {
+                        scopes.push(Scope::default());
+                        continue;
+                    }
+                    let functions = &mut compilation_units.last_mut().unwrap().functions;
+                    functions.push(func);
+                    scopes.push(Scope {
+                        function: Some(functions.len() - 1),
+                        ranges: Vec::new(),
+                    });
+                    continue;
                 }
-                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
-                    let mut name = String::new();
-                    let mut entity_type: Option<Type> = None;
-                    let mut location: Option<Location> = None;
-                    let mut line_number = 0;
-                    let mut attrs = entry.attrs();
-                    while let Some(attr) = attrs.next()? {
-                        let val = get_attr_value(&attr, &unit, &dwarf);
-                        //println!("   {}: {:?}", attr.name(), val);
-                        match attr.name() {
-                            gimli::DW_AT_name => {
-                                if let Ok(DebugValue::Str(attr_name)) = val {
-                                    name = attr_name;
-                                }
-                            }
-                            gimli::DW_AT_type => {
-                                if let Ok(DebugValue::Size(offset)) = val {
-                                    if let Some(dtype) = offset_to_type.get(&offset).clone() {
-                                        entity_type = Some(dtype.clone());
-                                    }
-                                }
-                            }
-                            gimli::DW_AT_location => {
-                                if let Some(loc) = get_location(&attr, &unit) {
-                                    location = Some(loc);
-                                }
-                            }
-                            gimli::DW_AT_decl_line => {
-                                if let Ok(DebugValue::Uint(num)) = val {
-                                    line_number = num;
-                                }
+                gimli::DW_TAG_lexical_block | gimli::DW_TAG_inlined_subroutine => {
+                    if scope.function.is_some() {
+                        let ranges = get_ranges(entry, &unit, &dwarf);
+                        if entry.tag() == gimli::DW_TAG_inlined_subroutine {
+                            // breakpoints on the function go into its inlined copies too
+                            let name = get_origin(entry, &unit).and_then(|origin| {
+                                get_attr_str(&origin, gimli::DW_AT_name, &unit, &dwarf)
+                            });
+                            if let (Some(name), Some(&(start, _))) = (name, ranges.first()) {
+                                let file = compilation_units.last_mut().unwrap();
+                                file.inlined_functions.push((name, start));
                             }
-                            _ => {}
                         }
+                        scopes.push(Scope {
+                            function: scope.function,
+                            ranges,
+                        });
+                        continue;
                     }
-                    if entity_type.is_some() && location.is_some() {
-                        let var = Variable {
-                            name,
-                            entity_type: entity_type.unwrap(),
-                            location: location.unwrap(),
-                            line_number: line_number.try_into().unwrap(),
-                        };
+                }
+                gimli::DW_TAG_formal_parameter | gimli::DW_TAG_variable => {
+                    if let Some(mut var) = get_variable(entry, &unit, &dwarf, &types) {
+                        // the parameters of inlined calls are locals of the caller
+                        var.is_parameter &= scope.ranges.is_empty();
+                        var.scope = scope.ranges.clone();
+                        let file = compilation_units.last_mut().unwrap();
                         if depth == 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .global_variables
-                                .push(var);
-                        } else if depth > 1 {
-                            compilation_units
-                                .last_mut()
-                                .unwrap()
-                                .functions
-                                .last_mut()
-                                .unwrap()
-                                .variables
-                                .push(var);
+                            // skip extern declarations
+                            if !matches!(var.location, Location::List(ref entries) if entries.is_empty())
+                            {
+                                file.global_variables.push(var);
+                            }
+                        } else if let Some(function) = scope.function {
+                            file.functions[function].variables.push(var);
                         }
                     }
                 }
@@ -198,6 +193,7 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                 // match statement
                 _ => {}
             }
+            scopes.push(scope);
         }
 
         // Get line numbers
@@ -220,10 +216,12 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                         );
                     }
 
-                    // Get the File
-                    let file = compilation_units
-                        .iter_mut()
-                        .find(|f| f.name == path.as_os_str().to_str().unwrap());
+                    // Get the File. The line program may spell out the full path (joined with
+                    // the compilation directory) while the unit's name is relative.
+                    let path = path.as_os_str().to_str().unwrap();
+                    let file = compilation_units.iter_mut().find(|f| {
+                        f.name == path || (!f.name.starts_with('/') && path.ends_with(&format!("/{}", f.name)))
+                    });
 
                     // Determine line/column. DWARF line/column is never 0, so we use that
                     // but other applications may want to display this differently.
@@ -234,13 +232,514 @@ pub fn load_file(object: &object::File, endian: gimli::RunTimeEndian) -> Result<
                             file: file.name.clone(),
                             number: line.try_into().unwrap(),
                             address: row.address().try_into().unwrap(),
+                            is_stmt: row.is_stmt(),
                         });
                     }
                 }
             }
         }
     }
-    Ok(compilation_units)
+    Ok((compilation_units, types))
+}
+
+/// The function and lexical block (or inlined subroutine) a DIE is nested in.
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// Index of the function in its compilation unit
+    function: Option<usize>,
+    /// Code ranges of the innermost block, or empty for the whole function
+    ranges: Vec<(usize, usize)>,
+}
+
+/// Returns the DIE that `entry` was derived from (DW_AT_abstract_origin, as for inlined
+/// functions and their variables, or DW_AT_specification).
+fn get_origin<'a, R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &'a gimli::Unit<R>,
+) -> Option<gimli::DebuggingInformationEntry<'a, 'a, R>> {
+    let attr = entry
+        .attr(gimli::DW_AT_abstract_origin)
+        .ok()?
+        .or(entry.attr(gimli::DW_AT_specification).ok()?)?;
+    match attr.value() {
+        gimli::AttributeValue::UnitRef(offset) => unit.entry(offset).ok(),
+        _ => None,
+    }
+}
+
+/// Returns the code ranges covered by a function or block.
+fn get_ranges<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Vec<(usize, usize)> {
+    let mut ranges = Vec::new();
+    if let Ok(mut iter) = dwarf.die_ranges(unit, entry) {
+        while let Ok(Some(range)) = iter.next() {
+            ranges.push((range.begin as usize, range.end as usize));
+        }
+    }
+    ranges
+}
+
+/// Reads a variable or parameter DIE, taking whatever it lacks from its abstract origin.
+fn get_variable<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    types: &HashMap<usize, Type>,
+) -> Option<Variable> {
+    let origin = get_origin(entry, unit);
+    let from_either = |name| {
+        entry
+            .attr(name)
+            .ok()
+            .and_then(|attr| attr)
+            .or_else(|| origin.as_ref()?.attr(name).ok()?)
+    };
+    let name = match get_attr_value(&from_either(gimli::DW_AT_name)?, unit, dwarf) {
+        Ok(DebugValue::Str(name)) => name,
+        _ => return None,
+    };
+    let entity_type = match get_attr_value(&from_either(gimli::DW_AT_type)?, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => types.get(&offset)?.clone(),
+        _ => return None,
+    };
+    let line_number = match from_either(gimli::DW_AT_decl_line).map(|attr| attr.udata_value()) {
+        Some(Some(line_number)) => line_number as usize,
+        _ => 0,
+    };
+    let location = if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_location) {
+        get_location(&attr, unit, dwarf)?
+    } else if let Ok(Some(attr)) = entry.attr(gimli::DW_AT_const_value) {
+        // the variable was optimized into a constant
+        let bytes = match attr.value() {
+            gimli::AttributeValue::Block(block) => block.to_slice().ok()?.into_owned(),
+            gimli::AttributeValue::Sdata(value) => value.to_le_bytes().to_vec(),
+            _ => attr.udata_value()?.to_le_bytes().to_vec(),
+        };
+        Location::Constant(bytes)
+    } else {
+        // optimized out entirely
+        Location::List(Vec::new())
+    };
+    Some(Variable {
+        name,
+        entity_type,
+        location,
+        line_number,
+        is_parameter: entry.tag() == gimli::DW_TAG_formal_parameter,
+        scope: Vec::new(),
+    })
+}
+
+/// Returns the id of the type defined by the DIE at `offset`: its .debug_info offset.
+fn type_id<R: Reader>(offset: UnitOffset, unit: &gimli::Unit<R>) -> usize {
+    match offset.to_unit_section_offset(unit) {
+        UnitSectionOffset::DebugInfoOffset(goff) => goff.0,
+        UnitSectionOffset::DebugTypesOffset(goff) => goff.0,
+    }
+}
+
+fn get_attr_str<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<String> {
+    match get_attr_value(&entry.attr(name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Str(s)) => Some(s),
+        _ => None,
+    }
+}
+
+fn get_attr_ref<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<usize> {
+    match get_attr_value(&entry.attr(name).ok()??, unit, dwarf) {
+        Ok(DebugValue::Size(offset)) => Some(offset),
+        _ => None,
+    }
+}
+
+/// Reads a constant attribute, whatever form it was encoded in.
+fn get_attr_const<R: Reader>(
+    entry: &gimli::DebuggingInformationEntry<R>,
+    name: gimli::DwAt,
+    unit: &gimli::Unit<R>,
+) -> Option<i64> {
+    let attr = entry.attr(name).ok()??;
+    match attr.value() {
+        gimli::AttributeValue::Sdata(value) => Some(value),
+        // e.g. DW_OP_plus_uconst, for DW_AT_data_member_location in old DWARF versions
+        gimli::AttributeValue::Exprloc(ref expr) => {
+            let mut pc = expr.0.clone();
+            match gimli::Operation::parse(&mut pc, unit.encoding()) {
+                Ok(gimli::Operation::PlusConstant { value }) => Some(value as i64),
+                _ => None,
+            }
+        }
+        _ => attr.udata_value().map(|value| value as i64),
+    }
+}
+
+/// Adds the types defined in `unit` to `types`. References between types are kept as ids, and
+/// names and sizes that depend on other types are filled in once the whole unit is read.
+fn load_types<R: Reader>(
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+    types: &mut HashMap<usize, Type>,
+) -> Result<(), Error> {
+    let mut unit_types = Vec::new();
+    // the type DIE enclosing each level of the tree, if any
+    let mut parents: Vec<Option<usize>> = Vec::new();
+    let mut depth: isize = 0;
+    let mut entries = unit.entries();
+    while let Some((delta_depth, entry)) = entries.next_dfs()? {
+        depth += delta_depth;
+        parents.truncate(depth as usize);
+        let parent = parents.last().cloned().unwrap_or(None);
+        let id = type_id(entry.offset(), unit);
+        let name = get_attr_str(entry, gimli::DW_AT_name, unit, dwarf);
+        let target = get_attr_ref(entry, gimli::DW_AT_type, unit, dwarf);
+        let byte_size =
+            get_attr_const(entry, gimli::DW_AT_byte_size, unit).map(|size| size as usize);
+        let tagged_name = |tag: &str| match name {
+            Some(ref name) => format!("{} {}", tag, name),
+            None => format!("{} {{...}}", tag),
+        };
+        let new_type = match entry.tag() {
+            gimli::DW_TAG_base_type | gimli::DW_TAG_unspecified_type => Some(Type {
+                name: name.clone().unwrap_or_else(|| "<unknown>".to_string()),
+                size: byte_size.unwrap_or(0),
+                kind: TypeKind::Base,
+            }),
+            gimli::DW_TAG_pointer_type | gimli::DW_TAG_reference_type => Some(Type {
+                name: String::new(),
+                size: byte_size.unwrap_or(unit.encoding().address_size as usize),
+                kind: TypeKind::Pointer(target),
+            }),
+            gimli::DW_TAG_structure_type | gimli::DW_TAG_class_type => Some(Type {
+                name: tagged_name("struct"),
+                size: byte_size.unwrap_or(0),
+                kind: TypeKind::Struct(Vec::new()),
+            }),
+            gimli::DW_TAG_union_type => Some(Type {
+                name: tagged_name("union"),
+                size: byte_size.unwrap_or(0),
+                kind: TypeKind::Union(Vec::new()),
+            }),
+            gimli::DW_TAG_enumeration_type => Some(Type {
+                name: tagged_name("enum"),
+                size: byte_size.unwrap_or(0),
+                kind: TypeKind::Enum(Vec::new()),
+            }),
+            gimli::DW_TAG_array_type => target.map(|element| Type {
+                name: String::new(),
+                size: 0,
+                kind: TypeKind::Array(element, Vec::new()),
+            }),
+            gimli::DW_TAG_typedef => Some(Type {
+                name: name.clone().unwrap_or_default(),
+                size: 0,
+                kind: TypeKind::Alias(target),
+            }),
+            gimli::DW_TAG_const_type => Some(qualified_type("const", target)),
+            gimli::DW_TAG_volatile_type => Some(qualified_type("volatile", target)),
+            gimli::DW_TAG_restrict_type => Some(qualified_type("restrict", target)),
+            gimli::DW_TAG_atomic_type => Some(qualified_type("_Atomic", target)),
+            gimli::DW_TAG_subroutine_type => Some(Type {
+                name: String::new(),
+                size: 1,
+                kind: TypeKind::Function(target, Vec::new()),
+            }),
+            _ => None,
+        };
+        if let Some(new_type) = new_type {
+            types.insert(id, new_type);
+            unit_types.push(id);
+            parents.push(Some(id));
+            continue;
+        }
+        parents.push(None);
+
+        // children of types: members, enumerators, array bounds and parameters
+        let parent_type = match parent.and_then(|parent| types.get_mut(&parent)) {
+            Some(parent_type) => parent_type,
+            None => continue,
+        };
+        match (entry.tag(), &mut parent_type.kind) {
+            (gimli::DW_TAG_member, TypeKind::Struct(ref mut members))
+            | (gimli::DW_TAG_member, TypeKind::Union(ref mut members)) => {
+                let type_id = match target {
+                    Some(type_id) => type_id,
+                    None => continue,
+                };
+                let offset =
+                    get_attr_const(entry, gimli::DW_AT_data_member_location, unit).unwrap_or(0);
+                let bits = match get_attr_const(entry, gimli::DW_AT_bit_size, unit) {
+                    Some(bit_size) => {
+                        let bit_offset =
+                            match get_attr_const(entry, gimli::DW_AT_data_bit_offset, unit) {
+                                Some(bit_offset) => bit_offset,
+                                None => {
+                                    // DWARF 2-4 count from the most significant bit of the storage
+                                    // unit, which on little-endian machines is at the end
+                                    let storage_bits = byte_size.unwrap_or(0) as i64 * 8;
+                                    let msb_offset =
+                                        get_attr_const(entry, gimli::DW_AT_bit_offset, unit)
+                                            .unwrap_or(0);
+                                    offset * 8 + storage_bits - msb_offset - bit_size
+                                }
+                            };
+                        // bad DWARF would make us shift by nonsense amounts
+                        if bit_size <= 0 || bit_size > 64 || bit_offset < 0 {
+                            continue;
+                        }
+                        Some((bit_offset as usize, bit_size as usize))
+                    }
+                    None => None,
+                };
+                members.push(Member {
+                    name: name.unwrap_or_default(),
+                    type_id,
+                    offset: offset as usize,
+                    bits,
+                });
+            }
+            (gimli::DW_TAG_enumerator, TypeKind::Enum(ref mut enumerators)) => {
+                let value = get_attr_const(entry, gimli::DW_AT_const_value, unit).unwrap_or(0);
+                enumerators.push((name.unwrap_or_default(), value));
+            }
+            (gimli::DW_TAG_subrange_type, TypeKind::Array(_, ref mut dims)) => {
+                let len = match get_attr_const(entry, gimli::DW_AT_count, unit) {
+                    Some(count) => Some(count as usize),
+                    None => get_attr_const(entry, gimli::DW_AT_upper_bound, unit)
+                        .map(|upper_bound| (upper_bound + 1) as usize),
+                };
+                dims.push(len);
+            }
+            (gimli::DW_TAG_formal_parameter, TypeKind::Function(_, ref mut params)) => {
+                if let Some(param) = target {
+                    params.push(param);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let resolved: Vec<(usize, String, usize)> = unit_types
+        .iter()
+        .map(|id| {
+            let name = resolve_type_name(types, *id, "", 0);
+            (*id, name, resolve_type_size(types, *id, 0))
+        })
+        .collect();
+    for (id, name, size) in resolved {
+        let ty = types.get_mut(&id).unwrap();
+        ty.name = name;
+        ty.size = size;
+    }
+    Ok(())
+}
+
+/// Spells out the C name of a type, with `declarator` (e.g. "*" for a pointer to it) placed
+/// where C wants it.
+fn resolve_type_name(
+    types: &HashMap<usize, Type>,
+    id: usize,
+    declarator: &str,
+    depth: usize,
+) -> String {
+    let ty = match types.get(&id) {
+        Some(ty) if depth < MAX_TYPE_DEPTH => ty,
+        _ => return join_declarator("<unknown>", declarator),
+    };
+    let target_name = |target: Option<usize>, declarator: &str| match target {
+        Some(target) => resolve_type_name(types, target, declarator, depth + 1),
+        None => join_declarator("void", declarator),
+    };
+    match ty.kind {
+        TypeKind::Pointer(target) => target_name(target, &format!("*{}", declarator)),
+        TypeKind::Array(element, ref dims) => {
+            let declarator = if declarator.is_empty() {
+                String::new()
+            } else {
+                format!("({})", declarator)
+            };
+            let dims: String = dims
+                .iter()
+                .map(|dim| match dim {
+                    Some(len) => format!("[{}]", len),
+                    None => "[]".to_string(),
+                })
+                .collect();
+            target_name(Some(element), &format!("{}{}", declarator, dims))
+        }
+        TypeKind::Function(ret, ref params) => {
+            let params: Vec<String> = params
+                .iter()
+                .map(|param| resolve_type_name(types, *param, "", depth + 1))
+                .collect();
+            let declarator = if declarator.is_empty() {
+                String::new()
+            } else {
+                format!("({})", declarator)
+            };
+            target_name(ret, &format!("{}({})", declarator, params.join(", ")))
+        }
+        TypeKind::Qualified(target) => {
+            // the name of a qualified type is just the qualifier until it is resolved
+            let inner = target_name(target, "");
+            let qualified = if inner.ends_with('*') {
+                // a qualified pointer: int * const
+                format!("{} {}", inner, ty.name)
+            } else {
+                format!("{} {}", ty.name, inner)
+            };
+            join_declarator(&qualified, declarator)
+        }
+        _ => join_declarator(&ty.name, declarator),
+    }
+}
+
+fn join_declarator(base: &str, declarator: &str) -> String {
+    if declarator.is_empty() {
+        base.to_string()
+    } else {
+        format!("{} {}", base, declarator)
+    }
+}
+
+fn qualified_type(qualifier: &str, target: Option<usize>) -> Type {
+    Type {
+        name: qualifier.to_string(),
+        size: 0,
+        kind: TypeKind::Qualified(target),
+    }
+}
+
+fn resolve_type_size(types: &HashMap<usize, Type>, id: usize, depth: usize) -> usize {
+    let ty = match types.get(&id) {
+        Some(ty) if depth < MAX_TYPE_DEPTH => ty,
+        _ => return 0,
+    };
+    match ty.kind {
+        TypeKind::Alias(Some(target)) | TypeKind::Qualified(Some(target)) => {
+            resolve_type_size(types, target, depth + 1)
+        }
+        TypeKind::Alias(None) | TypeKind::Qualified(None) => 0,
+        TypeKind::Array(element, ref dims) => {
+            let len: usize = dims.iter().map(|dim| dim.unwrap_or(0)).product();
+            len * resolve_type_size(types, element, depth + 1)
+        }
+        _ => ty.size,
+    }
+}
+
+/// x86-64 DWARF register numbers
+const DW_REG_RBP: u16 = 6;
+const DW_REG_RSP: u16 = 7;
+const DW_REG_RA: u16 = 16;
+
+/// The call frame information (.eh_frame and .debug_frame) of an executable, used to unwind the
+/// stack without relying on frame pointers.
+#[derive(Default)]
+pub struct CallFrameInfo {
+    eh_frame: Vec<u8>,
+    eh_frame_addr: u64,
+    debug_frame: Vec<u8>,
+    text_addr: u64,
+    endian: gimli::RunTimeEndian,
+}
+
+/// The part of a CFI table row deet understands: the canonical frame address (CFA) is
+/// `cfa_register + cfa_offset`, and the caller's rbp and return address were saved at the given
+/// offsets from the CFA (None if they weren't saved).
+#[derive(Debug, Clone, Copy)]
+pub struct FrameRule {
+    pub cfa_register: u16,
+    pub cfa_offset: i64,
+    pub rbp_offset: Option<i64>,
+    pub ra_offset: Option<i64>,
+}
+
+impl FrameRule {
+    pub fn cfa_is_rsp(&self) -> bool {
+        self.cfa_register == DW_REG_RSP
+    }
+
+    pub fn cfa_is_rbp(&self) -> bool {
+        self.cfa_register == DW_REG_RBP
+    }
+}
+
+impl CallFrameInfo {
+    pub fn load(object: &object::File, endian: gimli::RunTimeEndian) -> CallFrameInfo {
+        let section_data = |name: &str| -> Vec<u8> {
+            object
+                .section_data_by_name(name)
+                .map(|data| data.into_owned())
+                .unwrap_or_default()
+        };
+        let section_addr = |name: &str| -> u64 {
+            object
+                .section_by_name(name)
+                .map(|section| object::ObjectSection::address(&section))
+                .unwrap_or(0)
+        };
+        CallFrameInfo {
+            eh_frame: section_data(".eh_frame"),
+            eh_frame_addr: section_addr(".eh_frame"),
+            debug_frame: section_data(".debug_frame"),
+            text_addr: section_addr(".text"),
+            endian,
+        }
+    }
+
+    /// Returns the unwind rule for `address`, preferring .eh_frame (which is what gcc emits by
+    /// default) over .debug_frame.
+    pub fn find_rule(&self, address: u64) -> Option<FrameRule> {
+        let bases = gimli::BaseAddresses::default()
+            .set_eh_frame(self.eh_frame_addr)
+            .set_text(self.text_addr);
+        let eh_frame = gimli::EhFrame::new(&self.eh_frame, self.endian);
+        if let Some(rule) = find_rule_in(&eh_frame, &bases, address) {
+            return Some(rule);
+        }
+        let mut debug_frame = gimli::DebugFrame::new(&self.debug_frame, self.endian);
+        debug_frame.set_address_size(8);
+        find_rule_in(&debug_frame, &bases, address)
+    }
+}
+
+fn find_rule_in<R: gimli::Reader, S: gimli::UnwindSection<R>>(
+    section: &S,
+    bases: &gimli::BaseAddresses,
+    address: u64,
+) -> Option<FrameRule> {
+    let mut ctx = gimli::UninitializedUnwindContext::new();
+    let row = section
+        .unwind_info_for_address(bases, &mut ctx, address, S::cie_from_offset)
+        .ok()?;
+    let (cfa_register, cfa_offset) = match *row.cfa() {
+        gimli::CfaRule::RegisterAndOffset { register, offset } => (register.0, offset),
+        // e.g. PLT entries; not worth a DWARF expression evaluator
+        gimli::CfaRule::Expression(_) => return None,
+    };
+    let saved_at = |register| match row.register(gimli::Register(register)) {
+        gimli::RegisterRule::Offset(offset) => Some(offset),
+        _ => None,
+    };
+    Some(FrameRule {
+        cfa_register,
+        cfa_offset,
+        rbp_offset: saved_at(DW_REG_RBP),
+        ra_offset: saved_at(DW_REG_RA),
+    })
 }
 
 #[derive(Debug, Clone)]
@@ -291,25 +790,132 @@ impl<'input, Endian> Reader for gimli::EndianSlice<'input, Endian> where
 
 trait Reader: gimli::Reader<Offset = usize> + Send + Sync {}
 
-fn get_location<R: Reader>(attr: &gimli::Attribute<R>, unit: &gimli::Unit<R>) -> Option<Location> {
+fn get_location<R: Reader>(
+    attr: &gimli::Attribute<R>,
+    unit: &gimli::Unit<R>,
+    dwarf: &gimli::Dwarf<R>,
+) -> Option<Location> {
     if let gimli::AttributeValue::Exprloc(ref data) = attr.value() {
         let encoding = unit.encoding();
         let mut pc = data.0.clone();
         if pc.len() > 0 {
+            // the common cases, which don't need the full evaluator
             if let Ok(op) = gimli::Operation::parse(&mut pc, encoding) {
                 match op {
-                    gimli::Operation::FrameOffset { offset } => {
+                    gimli::Operation::FrameOffset { offset } if pc.is_empty() => {
                         return Some(Location::FramePointerOffset(offset.try_into().unwrap()));
                     }
-                    gimli::Operation::Address { address } => {
+                    gimli::Operation::Address { address } if pc.is_empty() => {
                         return Some(Location::Address(address.try_into().unwrap()));
                     }
                     _ => {}
                 }
             }
+            return Some(Location::Expression(DwarfExpression::new(&data.0, encoding)?));
+        }
+        return None;
+    }
+    // a location list, for values that move around
+    let mut locations = dwarf.attr_locations(unit, attr.value()).ok()??;
+    let mut entries = Vec::new();
+    while let Ok(Some(entry)) = locations.next() {
+        if let Some(expression) = DwarfExpression::new(&entry.data.0, unit.encoding()) {
+            entries.push((
+                entry.range.begin as usize,
+                entry.range.end as usize,
+                expression,
+            ));
         }
     }
-    None
+    Some(Location::List(entries))
+}
+
+/// A DWARF location expression, kept to be evaluated once the program is running.
+#[derive(Clone)]
+pub struct DwarfExpression {
+    bytecode: Vec<u8>,
+    encoding: gimli::Encoding,
+    /// Added to the addresses in the expression (DW_OP_addr) once we know where the program was
+    /// loaded
+    load_bias: usize,
+}
+
+impl DwarfExpression {
+    fn new<R: Reader>(bytecode: &R, encoding: gimli::Encoding) -> Option<Self> {
+        Some(DwarfExpression {
+            bytecode: bytecode.to_slice().ok()?.into_owned(),
+            encoding,
+            load_bias: 0,
+        })
+    }
+
+    pub fn set_load_bias(&mut self, load_bias: usize) {
+        self.load_bias = load_bias;
+    }
+
+    /// Evaluates the expression in the given frame.
+    pub fn evaluate(&self, frame: &dyn FrameState) -> Place {
+        self.try_evaluate(frame).unwrap_or(Place::OptimizedOut)
+    }
+
+    fn try_evaluate(&self, frame: &dyn FrameState) -> Option<Place> {
+        let bytecode = gimli::EndianSlice::new(&self.bytecode, gimli::LittleEndian);
+        let mut evaluation = gimli::Expression(bytecode).evaluation(self.encoding);
+        let mut result = evaluation.evaluate().ok()?;
+        loop {
+            result = match result {
+                gimli::EvaluationResult::Complete => break,
+                gimli::EvaluationResult::RequiresMemory { address, size, .. } => {
+                    let bytes = frame.read_memory(address as usize, size as usize)?;
+                    let mut raw = [0u8; 8];
+                    raw[..bytes.len().min(8)].copy_from_slice(&bytes[..bytes.len().min(8)]);
+                    let value = gimli::Value::Generic(u64::from_le_bytes(raw));
+                    evaluation.resume_with_memory(value).ok()?
+                }
+                gimli::EvaluationResult::RequiresRegister { register, .. } => {
+                    let value = gimli::Value::Generic(frame.register(register.0)?);
+                    evaluation.resume_with_register(value).ok()?
+                }
+                gimli::EvaluationResult::RequiresFrameBase => {
+                    // gcc always uses the CFA as the frame base on x86-64
+                    evaluation.resume_with_frame_base(frame.cfa() as u64).ok()?
+                }
+                gimli::EvaluationResult::RequiresCallFrameCfa => evaluation
+                    .resume_with_call_frame_cfa(frame.cfa() as u64)
+                    .ok()?,
+                gimli::EvaluationResult::RequiresRelocatedAddress(address) => evaluation
+                    .resume_with_relocated_address(address.wrapping_add(self.load_bias as u64))
+                    .ok()?,
+                gimli::EvaluationResult::RequiresBaseType(_) => evaluation
+                    .resume_with_base_type(gimli::ValueType::Generic)
+                    .ok()?,
+                // e.g. DW_OP_entry_value, which needs the registers at the time of the call
+                _ => return None,
+            };
+        }
+        let pieces = evaluation.result();
+        let single = pieces.len() == 1 && pieces[0].size_in_bits.is_none();
+        let mut places = Vec::new();
+        for piece in pieces {
+            let place = match piece.location {
+                gimli::Location::Empty => Place::OptimizedOut,
+                gimli::Location::Register { register } => Place::Register(register.0),
+                gimli::Location::Address { address } => Place::Memory(address as usize),
+                gimli::Location::Value { value } => {
+                    let value = value.to_u64(!0).ok()?;
+                    Place::Value(value.to_le_bytes().to_vec())
+                }
+                gimli::Location::Bytes { value } => Place::Value(value.to_vec()),
+                gimli::Location::ImplicitPointer { .. } => Place::OptimizedOut,
+            };
+            if single {
+                return Some(place);
+            }
+            let size = piece.size_in_bits.map_or(0, |bits| (bits / 8) as usize);
+            places.push((place, size));
+        }
+        Some(Place::Pieces(places))
+    }
 }
 
 // based on dwarf_dump.rs
@@ -344,6 +950,11 @@ fn get_attr_value<R: Reader>(
                 Ok(DebugValue::Str(format!("<.debug_str+0x{:08x}>", offset.0)))
             }
         }
+        // DWARF 5 puts names in .debug_line_str or behind .debug_str_offsets
+        gimli::AttributeValue::DebugLineStrRef(_) | gimli::AttributeValue::DebugStrOffsetsIndex(_) => {
+            let s = dwarf.attr_string(unit, value)?;
+            Ok(DebugValue::Str(format!("{}", s.to_string_lossy()?)))
+        }
         gimli::AttributeValue::Sdata(data) => Ok(DebugValue::Int(data)),
         gimli::AttributeValue::Addr(data) => Ok(DebugValue::Uint(data)),
         gimli::AttributeValue::Udata(data) => Ok(DebugValue::Uint(data)),
@@ -2,12 +2,15 @@ use std::collections::HashMap;
 use nix::sys::ptrace;
 use nix::sys::signal;
 use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
-use crate::dwarf_data::{DwarfData, Error as DwarfError};
+use crate::dwarf_data::{DwarfData, Error as DwarfError, FrameState, Place};
+use crate::registers;
+use crate::signals::{self, SignalTable};
+use nix::errno::Errno;
 use nix::unistd::Pid;
-use std::process::Child;
 use std::process::Command;
 use std::os::unix::process::CommandExt;
 use std::mem::size_of;
+use std::fs;
 
 #[derive(Debug)]
 pub enum Status {
@@ -21,6 +24,36 @@ pub enum Status {
     /// Indicates the inferior exited due to a signal. Contains the signal that killed the
     /// process.
     Signaled(signal::Signal),
+
+    /// Indicates a hardware watchpoint fired. Contains the debug register slot (0-3) and the
+    /// instruction pointer right after the instruction that wrote to the watched location.
+    Watchpoint(usize, usize),
+
+    /// Indicates the inferior forked and we are to follow the child. Contains the pid of the
+    /// child, which can be picked up with `take_forked`.
+    Forked(Pid),
+
+    /// Indicates the inferior called exec. Contains the instruction pointer at the entry of the
+    /// new program. Our breakpoints went away with the old address space.
+    Execed(usize),
+}
+
+impl Status {
+    /// Returns true if the inferior is stopped with its address space intact (as opposed to gone
+    /// or replaced by exec).
+    pub fn is_stopped(&self) -> bool {
+        match self {
+            Status::Stopped(_, _) | Status::Watchpoint(_, _) | Status::Forked(_) => true,
+            Status::Exited(_) | Status::Signaled(_) | Status::Execed(_) => false,
+        }
+    }
+}
+
+/// Which process to keep debugging when the inferior forks ("set follow-fork-mode").
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FollowForkMode {
+    Parent,
+    Child,
 }
 
 /// This function calls ptrace with PTRACE_TRACEME to enable debugging on a process. You should use
@@ -32,144 +65,1377 @@ fn child_traceme() -> Result<(), std::io::Error> {
     )))
 }
 
+/// Turns off address space randomization for the process, so that it is loaded at the same
+/// addresses on every run. Called in the child process like `child_traceme`.
+fn child_disable_randomization() -> Result<(), std::io::Error> {
+    // 0xffffffff queries the current persona without changing it
+    let persona = unsafe { libc::personality(0xffff_ffff) };
+    if persona < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    if unsafe { libc::personality(persona as libc::c_ulong | ADDR_NO_RANDOMIZE) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(())
+}
+
 const INT_CODE:u8 = 0xcc as u8;
+/// si_code of a SIGTRAP raised by an int3 (as opposed to a single step)
+const SI_KERNEL: i32 = 0x80;
+/// Personality flag that turns off address space randomization (see sys/personality.h)
+const ADDR_NO_RANDOMIZE: libc::c_ulong = 0x0040000;
+/// Auxiliary vector entry holding the address of the program's entry point
+const AT_ENTRY: u64 = 9;
+
+/// Number of hardware watchpoint slots (DR0-DR3)
+pub const NUM_WATCHPOINT_SLOTS: usize = 4;
+/// Offset of u_debugreg in struct user (see sys/user.h), for PTRACE_PEEKUSER/POKEUSER
+const DEBUGREG_OFFSET: usize = 848;
+const DR6: usize = 6;
+const DR7: usize = 7;
+
+/// Give up unwinding after this many frames (the stack is probably garbage by then)
+const MAX_FRAMES: usize = 1024;
+
+/// A stack frame found by the unwinder.
+#[derive(Debug, Clone)]
+pub struct Frame {
+    pub rip: usize,
+    pub rsp: usize,
+    pub rbp: usize,
+    /// Canonical frame address: the value of rsp before the call that created this frame. gcc
+    /// uses it as the frame base for local variables.
+    pub cfa: usize,
+}
+
+impl Frame {
+    /// Address to use for looking up the code of this frame. For callers, rip is a return
+    /// address, so we step back into the call instruction.
+    pub fn lookup_addr(&self, innermost: bool) -> usize {
+        if innermost {
+            self.rip
+        } else {
+            self.rip - 1
+        }
+    }
+}
+
+/// Error for reading a value that the compiler didn't keep around
+pub const OPTIMIZED_OUT: &str = "value has been optimized out";
+
+/// A stopped program whose memory and registers we can look at: a live inferior or a core
+/// dump. Unwinding and reading variables only go through this, so they work on both.
+pub trait ProgramState {
+    /// Returns the pid of the process.
+    fn pid(&self) -> Pid;
+
+    /// Returns the id of the current thread, whose registers we read.
+    fn tid(&self) -> Pid;
+
+    /// Returns the number and id of each thread, ordered by number.
+    fn thread_list(&self) -> Vec<(usize, Pid)>;
+
+    /// Makes thread number `num` the current one. Returns false if there is no such thread.
+    fn select_thread(&mut self, num: usize) -> bool;
+
+    /// Returns the number of the current thread.
+    fn current_thread_num(&self) -> usize;
+
+    /// Reads up to `len` bytes at `addr`, stopping early at the first unreadable page. Fails only
+    /// if nothing could be read.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error>;
+
+    /// Returns the general purpose registers of the current thread.
+    fn get_regs(&self) -> Result<libc::user_regs_struct, nix::Error>;
+
+    /// Reads the low quadword of an xmm register of the current thread, which is where doubles
+    /// and floats live.
+    fn read_xmm(&self, xmm: usize) -> Option<u64>;
+
+    /// Returns the address whose access made the current thread fault, if it stopped with
+    /// SIGSEGV or SIGBUS.
+    fn fault_address(&self) -> Option<usize>;
+
+    /// Returns the auxiliary vector the kernel passed to the program.
+    fn auxv(&self) -> Option<Vec<u8>>;
+
+    /// Returns the address the program's entry point was loaded at. Position independent
+    /// executables are loaded at a random offset from their link-time addresses.
+    fn entry_point(&self) -> Option<usize> {
+        let auxv = self.auxv()?;
+        auxv.chunks_exact(16).find_map(|entry| {
+            let mut key = [0u8; 8];
+            let mut value = [0u8; 8];
+            key.copy_from_slice(&entry[..8]);
+            value.copy_from_slice(&entry[8..]);
+            if u64::from_le_bytes(key) == AT_ENTRY {
+                Some(u64::from_le_bytes(value) as usize)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Reads exactly `len` bytes of memory starting at `addr`.
+    fn read_bytes(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let bytes = self.read_memory(addr, len)?;
+        if bytes.len() < len {
+            return Err(nix::Error::Sys(Errno::EIO));
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the value of the register called `name` (e.g. "rax", "eflags"), or None if there
+    /// is no such register.
+    fn get_register(&self, name: &str) -> Result<Option<u64>, nix::Error> {
+        let mut regs = self.get_regs()?;
+        Ok(registers::register_mut(&mut regs, name).map(|reg| *reg))
+    }
+
+    /// Returns the current instruction pointer.
+    fn get_rip(&self) -> Result<usize, nix::Error> {
+        Ok(self.get_regs()?.rip as usize)
+    }
+}
+
+impl<'a> dyn ProgramState + 'a {
+    /// Returns the context for evaluating variable locations in `frame`, which is frame number
+    /// `num` of the stack.
+    pub fn frame_context(&self, frame: &Frame, num: usize) -> FrameContext<'_> {
+        FrameContext {
+            program: self,
+            frame: frame.clone(),
+            regs: match num {
+                0 => self.get_regs().ok(),
+                _ => None,
+            },
+        }
+    }
+
+    /// Unwinds the stack using the CFI, falling back to the rbp chain for code that has none.
+    /// Stops after main, at the outermost frame, or when the stack stops making sense.
+    pub fn unwind(&self, debug_data: &DwarfData) -> Result<Vec<Frame>, nix::Error> {
+        let regs = self.get_regs()?;
+        let mut frame = Frame {
+            rip: regs.rip as usize,
+            rsp: regs.rsp as usize,
+            rbp: regs.rbp as usize,
+            cfa: 0,
+        };
+        let mut frames = Vec::new();
+        while frames.len() < MAX_FRAMES {
+            // a return address points after the call, which may already be past the end of the
+            // calling function (e.g. when calling a noreturn function)
+            let lookup_addr = frame.lookup_addr(frames.is_empty());
+            let read_word = |addr: usize| -> Option<usize> {
+                let bytes = self.read_bytes(addr, size_of::<usize>()).ok()?;
+                let mut word = [0u8; size_of::<usize>()];
+                word.copy_from_slice(&bytes);
+                Some(usize::from_le_bytes(word))
+            };
+            let caller = match debug_data.get_frame_rule(lookup_addr) {
+                Some(rule) => {
+                    let base = if rule.cfa_is_rsp() {
+                        frame.rsp
+                    } else if rule.cfa_is_rbp() {
+                        frame.rbp
+                    } else {
+                        0
+                    };
+                    frame.cfa = (base as i64 + rule.cfa_offset) as usize;
+                    let rip = rule
+                        .ra_offset
+                        .and_then(|off| read_word((frame.cfa as i64 + off) as usize));
+                    let rbp = match rule.rbp_offset {
+                        Some(off) => read_word((frame.cfa as i64 + off) as usize),
+                        // the callee didn't touch rbp
+                        None => Some(frame.rbp),
+                    };
+                    if base == 0 {
+                        None
+                    } else {
+                        rip.and_then(|rip| Some((rip, rbp?)))
+                    }
+                }
+                // no CFI: assume the usual "push %rbp; mov %rsp,%rbp" frame
+                None if frame.rbp != 0 => {
+                    frame.cfa = frame.rbp + 2 * size_of::<usize>();
+                    read_word(frame.rbp + size_of::<usize>())
+                        .and_then(|rip| Some((rip, read_word(frame.rbp)?)))
+                }
+                None => None,
+            };
+            let is_main = debug_data
+                .get_function_from_addr(lookup_addr)
+                .map_or(false, |f| f == "main");
+            let next = frame.clone();
+            frames.push(next);
+            match caller {
+                // the stack grows down, so the caller's frame must be above ours
+                Some((rip, rbp)) if !is_main && rip != 0 && frame.cfa > frame.rsp => {
+                    frame = Frame {
+                        rip,
+                        rsp: frame.cfa,
+                        rbp,
+                        cfa: 0,
+                    };
+                }
+                _ => break,
+            }
+        }
+        Ok(frames)
+    }
+
+    pub fn backtrace(&self, debug_data: &DwarfData) -> Result<(), nix::Error> {
+        for (i, frame) in self.unwind(debug_data)?.iter().enumerate() {
+            println!("{}", self.describe_frame(debug_data, i, frame));
+        }
+        Ok(())
+    }
+
+    /// Formats frame number `num` gdb-style, including the values of its arguments.
+    pub fn describe_frame(&self, debug_data: &DwarfData, num: usize, frame: &Frame) -> String {
+        let lookup_addr = frame.lookup_addr(num == 0);
+        let func = match debug_data.get_function_data_from_addr(lookup_addr) {
+            Some(func) => func,
+            // no debug info, but maybe a symbol
+            None => {
+                let from = match debug_data.get_library_at(lookup_addr) {
+                    Some(lib) => format!(" from {}", lib.path),
+                    None => String::new(),
+                };
+                return match debug_data.get_symbol_from_addr(lookup_addr) {
+                    Some((sym, _)) if frame.rip == sym.address => {
+                        format!("#{:<2} {:#018x} in {} (){}", num, frame.rip, sym.name, from)
+                    }
+                    Some((sym, _)) => format!(
+                        "#{:<2} {:#018x} in {}+{:#x} (){}",
+                        num,
+                        frame.rip,
+                        sym.name,
+                        frame.rip - sym.address,
+                        from
+                    ),
+                    None => format!("#{:<2} ?? ({:#018x}){}", num, frame.rip, from),
+                };
+            }
+        };
+        let context = self.frame_context(frame, num);
+        let args: Vec<String> = func
+            .variables
+            .iter()
+            .filter(|var| var.is_parameter)
+            .map(|var| {
+                let place = var.location.evaluate(&context);
+                let value = match context.read_place(&place, var.entity_type.size) {
+                    Ok(bytes) => debug_data.format_value(&var.entity_type, &bytes, &|addr, len| {
+                        // cores leave out read-only data, which is still in the executable
+                        self.read_memory(addr, len)
+                            .ok()
+                            .or_else(|| debug_data.get_file_bytes(addr, len))
+                    }),
+                    Err(ref err) if err == OPTIMIZED_OUT => "<optimized out>".to_string(),
+                    Err(_) => "<unavailable>".to_string(),
+                };
+                format!("{}={}", var.name, value)
+            })
+            .collect();
+        let location = match debug_data.get_line_from_addr(lookup_addr) {
+            Some(line) => format!(" at {}", line),
+            None => String::new(),
+        };
+        format!(
+            "#{:<2} {:#018x} in {} ({}){}",
+            num,
+            frame.rip,
+            func.name,
+            args.join(", "),
+            location
+        )
+    }
+}
+
+/// A frame of a stopped program, for evaluating the locations of its variables.
+pub struct FrameContext<'a> {
+    program: &'a dyn ProgramState,
+    frame: Frame,
+    /// Registers of the innermost frame. In outer frames we only know rip, rsp and rbp.
+    regs: Option<libc::user_regs_struct>,
+}
+
+impl<'a> FrameContext<'a> {
+    /// Reads `size` bytes of the value at `place`.
+    pub fn read_place(&self, place: &Place, size: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = match place {
+            Place::Memory(addr) => self
+                .program
+                .read_bytes(*addr, size)
+                .or(Err(format!("Cannot access memory at address {:#x}", addr)))?,
+            Place::Register(register) => self
+                .register(*register)
+                .ok_or(OPTIMIZED_OUT.to_string())?
+                .to_le_bytes()
+                .to_vec(),
+            Place::Value(bytes) => bytes.clone(),
+            Place::Pieces(pieces) => {
+                let mut bytes = Vec::new();
+                for (piece, piece_size) in pieces {
+                    let piece_size = match piece_size {
+                        0 => size.saturating_sub(bytes.len()),
+                        _ => *piece_size,
+                    };
+                    bytes.extend(self.read_place(piece, piece_size)?);
+                }
+                bytes
+            }
+            Place::OptimizedOut => return Err(OPTIMIZED_OUT.to_string()),
+        };
+        bytes.resize(size, 0);
+        Ok(bytes)
+    }
+}
+
+impl<'a> FrameState for FrameContext<'a> {
+    fn pc(&self) -> usize {
+        self.frame.lookup_addr(self.regs.is_some())
+    }
+
+    fn cfa(&self) -> usize {
+        self.frame.cfa
+    }
+
+    fn register(&self, register: u16) -> Option<u64> {
+        let name = registers::dwarf_register_name(register);
+        match (self.regs, name) {
+            (Some(mut regs), Some(name)) => {
+                registers::register_mut(&mut regs, name).map(|reg| *reg)
+            }
+            (None, Some("rip")) => Some(self.frame.rip as u64),
+            (None, Some("rsp")) => Some(self.frame.rsp as u64),
+            (None, Some("rbp")) => Some(self.frame.rbp as u64),
+            (Some(_), None) => self.program.read_xmm(registers::dwarf_xmm_register(register)?),
+            _ => None,
+        }
+    }
+
+    fn read_memory(&self, addr: usize, len: usize) -> Option<Vec<u8>> {
+        self.program.read_bytes(addr, len).ok()
+    }
+}
+
+/// A thread (LWP) of the inferior. Its registers live in the kernel, keyed by its tid.
+#[derive(Debug, Clone)]
+pub struct Thread {
+    /// gdb-style thread number, counting from 1 in order of creation
+    pub num: usize,
+    pub tid: Pid,
+    /// Whether the thread was resumed and hasn't reported a stop since
+    running: bool,
+    /// We sent the thread a SIGSTOP that hasn't arrived yet because it stopped for another reason
+    /// first. It stops again right after it is resumed.
+    sigstop_pending: bool,
+    /// Signal to deliver when the thread is resumed
+    pending_signal: Option<signal::Signal>,
+}
 
 #[derive(Debug)]
 pub struct Inferior {
-    child: Child,
+    pid: Pid,
+    threads: Vec<Thread>,
+    next_thread_num: usize,
+    /// The thread that last reported an event or was selected with "thread N"
+    current: Pid,
+    /// Events of processes and threads that aren't ours, at least not yet: the first SIGSTOP of
+    /// a new thread that arrives before the clone event of its creator, or events of the other
+    /// inferiors, which the debugger passes on with `hand_over_strays`
+    stray_stops: Vec<(Pid, WaitStatus)>,
+    /// Whether we attached to an existing process rather than spawning it
+    attached: bool,
     breakpoints: HashMap<usize, u8>,
+    /// Breakpoints that only last until the next stop ("finish", "until", "advance"). They are
+    /// also in `breakpoints`, unless the address held a breakpoint already.
+    internal_breakpoints: Vec<usize>,
+    follow_fork_mode: FollowForkMode,
+    /// Whether the process we don't follow after a fork is let go, or kept stopped
+    detach_on_fork: bool,
+    /// Forked processes we still trace, waiting to be picked up by `take_forked`
+    forked: Vec<Inferior>,
+    /// Breakpoints taken out while a vfork child borrows our memory
+    vfork_breakpoints: Vec<usize>,
+    /// Whether the last resume was a single step
+    stepping: bool,
+    /// Which signals stop the inferior and which are passed on to it
+    signal_table: SignalTable,
 }
 
 fn _align_addr_to_word(addr: usize) -> usize {
     addr & (-(size_of::<usize>() as isize) as usize)
 }
 
+/// Lists the threads of process `pid`.
+fn process_tids(pid: Pid) -> Vec<Pid> {
+    match fs::read_dir(format!("/proc/{}/task", pid)) {
+        Ok(tasks) => tasks
+            .filter_map(|task| task.ok()?.file_name().to_str()?.parse::<i32>().ok())
+            .map(Pid::from_raw)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
 impl Inferior {
+    fn from_pid(pid: Pid, attached: bool) -> Inferior {
+        Inferior {
+            pid,
+            threads: vec![Thread {
+                num: 1,
+                tid: pid,
+                running: false,
+                sigstop_pending: false,
+                pending_signal: None,
+            }],
+            next_thread_num: 2,
+            current: pid,
+            stray_stops: Vec::new(),
+            attached,
+            breakpoints: HashMap::new(),
+            internal_breakpoints: Vec::new(),
+            follow_fork_mode: FollowForkMode::Parent,
+            detach_on_fork: true,
+            forked: Vec::new(),
+            vfork_breakpoints: Vec::new(),
+            stepping: false,
+            signal_table: SignalTable::new(),
+        }
+    }
+
     fn write_byte(&mut self, addr: usize, val: u8) -> Result<u8, nix::Error> {
         let aligned_addr = _align_addr_to_word(addr);
         let byte_offset = addr - aligned_addr;
-        let word = ptrace::read(self.pid(), aligned_addr as ptrace::AddressType)? as u64;
+        let word = ptrace::read(self.tid(), aligned_addr as ptrace::AddressType)? as u64;
         let orig_byte = (word >> 8 * byte_offset) & 0xff;
         let masked_word = word & !(0xff << 8 * byte_offset);
         let updated_word = masked_word | ((val as u64) << 8 * byte_offset);
         ptrace::write(
-            self.pid(),
+            self.tid(),
             aligned_addr as ptrace::AddressType,
             updated_word as *mut std::ffi::c_void,
         )?;
         Ok(orig_byte as u8)
     }
 
+    /// Writes `bytes` at `addr`. Our breakpoints in the region stay in place: the new bytes
+    /// replace the original bytes saved for them instead.
+    pub fn write_bytes(&mut self, addr: usize, bytes: &[u8]) -> Result<(), nix::Error> {
+        let mut bytes = bytes.to_vec();
+        let mut new_orig_bytes = Vec::new();
+        for brk in self.breakpoints.keys() {
+            if *brk >= addr && *brk < addr + bytes.len() {
+                new_orig_bytes.push((*brk, bytes[*brk - addr]));
+                bytes[*brk - addr] = INT_CODE;
+            }
+        }
+        let mut word_addr = _align_addr_to_word(addr);
+        while word_addr < addr + bytes.len() {
+            let word = ptrace::read(self.tid(), word_addr as ptrace::AddressType)? as u64;
+            let mut word_bytes = word.to_le_bytes();
+            for (i, byte) in word_bytes.iter_mut().enumerate() {
+                let byte_addr = word_addr + i;
+                if byte_addr >= addr && byte_addr < addr + bytes.len() {
+                    *byte = bytes[byte_addr - addr];
+                }
+            }
+            ptrace::write(
+                self.tid(),
+                word_addr as ptrace::AddressType,
+                u64::from_le_bytes(word_bytes) as *mut std::ffi::c_void,
+            )?;
+            word_addr += size_of::<usize>();
+        }
+        self.breakpoints.extend(new_orig_bytes);
+        Ok(())
+    }
+
     pub fn append_breakpoint(&mut self, addr:usize) -> Result<u8, nix::Error>{
+        // writing 0xcc twice would save the 0xcc as the original byte
+        if let Some(orig_byte) = self.breakpoints.get(&addr) {
+            return Ok(*orig_byte);
+        }
         let orig_byte = self.write_byte(addr,INT_CODE)?;
         self.breakpoints.insert(addr, orig_byte);
         Ok(orig_byte)
     }
+
+    fn read_debugreg(&self, tid: Pid, reg: usize) -> Result<u64, nix::Error> {
+        let offset = DEBUGREG_OFFSET + reg * size_of::<u64>();
+        // PEEKUSER returns the value itself, so -1 is only an error if errno says so
+        let val = unsafe {
+            Errno::clear();
+            libc::ptrace(
+                libc::PTRACE_PEEKUSER,
+                tid.as_raw(),
+                offset as *mut std::ffi::c_void,
+                std::ptr::null_mut::<std::ffi::c_void>(),
+            )
+        };
+        if val == -1 && Errno::last() != Errno::UnknownErrno {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        Ok(val as u64)
+    }
+
+    fn write_debugreg(&self, tid: Pid, reg: usize, val: u64) -> Result<(), nix::Error> {
+        let offset = DEBUGREG_OFFSET + reg * size_of::<u64>();
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_POKEUSER,
+                tid.as_raw(),
+                offset as *mut std::ffi::c_void,
+                val as *mut std::ffi::c_void,
+            )
+        };
+        Errno::result(res).map(|_| ())
+    }
+
+    /// Arms hardware watchpoint `slot` (0-3) to trap on writes to the `len` bytes at `addr`. `len`
+    /// must be 1, 2, 4 or 8 and `addr` aligned to it.
+    pub fn set_watchpoint(
+        &mut self,
+        slot: usize,
+        addr: usize,
+        len: usize,
+    ) -> Result<(), nix::Error> {
+        let len_bits: u64 = match len {
+            1 => 0b00,
+            2 => 0b01,
+            4 => 0b11,
+            8 => 0b10,
+            _ => return Err(nix::Error::Sys(Errno::EINVAL)),
+        };
+        // DR0-DR3 hold the address, DR7 the enable bit (L0-L3), the condition (01 = break on
+        // writes) and the length. Every thread has its own debug registers.
+        for tid in self.thread_ids() {
+            self.write_debugreg(tid, slot, addr as u64)?;
+            let mut dr7 = self.read_debugreg(tid, DR7)?;
+            dr7 &= !(0b1111 << (16 + slot * 4));
+            dr7 |= (0b01 | (len_bits << 2)) << (16 + slot * 4);
+            dr7 |= 1 << (slot * 2);
+            self.write_debugreg(tid, DR7, dr7)?;
+        }
+        Ok(())
+    }
+
+    /// Disarms hardware watchpoint `slot`.
+    pub fn clear_watchpoint(&mut self, slot: usize) -> Result<(), nix::Error> {
+        for tid in self.thread_ids() {
+            let dr7 = self.read_debugreg(tid, DR7)?;
+            self.write_debugreg(tid, DR7, dr7 & !(1 << (slot * 2)))?;
+        }
+        Ok(())
+    }
+
+    /// Gives the new thread `tid` the watchpoints of thread `from`, as threads don't inherit
+    /// debug registers.
+    fn copy_watchpoints(&self, from: Pid, tid: Pid) -> Result<(), nix::Error> {
+        let dr7 = self.read_debugreg(from, DR7)?;
+        if dr7 == 0 {
+            return Ok(());
+        }
+        for slot in 0..NUM_WATCHPOINT_SLOTS {
+            self.write_debugreg(tid, slot, self.read_debugreg(from, slot)?)?;
+        }
+        self.write_debugreg(tid, DR7, dr7)
+    }
+
+    /// Removes the breakpoint at `addr`, restoring the original byte.
+    pub fn remove_breakpoint(&mut self, addr: usize) -> Result<(), nix::Error> {
+        if let Some(orig_byte) = self.breakpoints.remove(&addr) {
+            self.write_byte(addr, orig_byte)?;
+        }
+        Ok(())
+    }
     /// Attempts to start a new inferior process. Returns Some(Inferior) if successful, or None if
-    /// an error is encountered.
-    pub fn new(target: &str, args: &Vec<String>, breakpoints:&Vec<usize>) -> Option<Inferior> {
+    /// an error is encountered. The process is stopped before its first instruction, with no
+    /// breakpoints yet, since we only know where it was loaded from now on.
+    pub fn new(target: &str, args: &Vec<String>, disable_randomization: bool) -> Option<Inferior> {
         let mut _binding = Command::new(target);
         let cmd = _binding.args(args);
+        if disable_randomization {
+            unsafe {
+                cmd.pre_exec(child_disable_randomization);
+            }
+        }
         unsafe { cmd.pre_exec(child_traceme);}
         let child = cmd.spawn().ok()?;
-        let mut res = Inferior{child, breakpoints: HashMap::new()};
+        let pid = Pid::from_raw(child.id() as i32);
+        let mut res = Inferior::from_pid(pid, false);
         if res.wait(Some(WaitPidFlag::WSTOPPED)).is_ok(){
-            for (i,brk) in breakpoints.iter().enumerate(){
-                match Inferior::write_byte(&mut res, *brk, INT_CODE){
-                    Err(_) => {
-                        println!("Error when injecting breakpoint {}, addr {}",i, brk);
-                        return None;
-                    }
-                    Ok(v) => {
-                        res.breakpoints.insert(*brk,v);
-                    }
-                }
-            }
+            res.set_trace_options().ok()?;
             Some(res)
         }else {
             None
         }
     }
 
-    pub fn cont(&mut self) -> Result<Status, nix::Error>{
-        let mut regs = ptrace::getregs(self.pid()).unwrap();
-        let rip = regs.rip as usize;
-        if let Some(orig_byte) = self.breakpoints.get(&(rip-1)){
-            // 1. restore and rewind
-            self.write_byte(rip-1, *orig_byte)?;
-            regs.rip -= 1;
-            ptrace::setregs(self.pid(),regs)?;
-            // 2. step and wait
-            ptrace::step(self.pid(), None)?;
-            match self.wait(None)?{
-                Status::Exited(exit_code) => return Ok(Status::Exited(exit_code)),
-                Status::Signaled(signal) => return Ok(Status::Signaled(signal)),
-                Status::Stopped(signal, _rip) => {
-                    if signal == signal::SIGTRAP {
-                        self.write_byte(rip-1, INT_CODE)?;
-                    } else{
-                        println!("SINGLESTEP return a unknown signal {}", signal);
+    /// Attaches to the running process `pid` and stops it. Returns None if ptrace refuses (e.g.
+    /// no permission, or someone else is tracing it).
+    pub fn attach(pid: Pid) -> Option<Inferior> {
+        ptrace::attach(pid).ok()?;
+        let mut res = Inferior::from_pid(pid, true);
+        // PTRACE_ATTACH sends a SIGSTOP; wait for it to land
+        match res.wait(None) {
+            Ok(Status::Stopped(_, _)) => {}
+            _ => return None,
+        }
+        // the SIGSTOP is ours, so the program mustn't get it when it resumes
+        if let Some(thread) = res.thread_mut(pid) {
+            thread.pending_signal = None;
+        }
+        res.attach_threads();
+        res.set_trace_options().ok()?;
+        Some(res)
+    }
+
+    /// Attaches to the other threads of the process. Threads may be spawned while we're at it,
+    /// so we look again until there are no new ones.
+    fn attach_threads(&mut self) {
+        loop {
+            let new_tids: Vec<Pid> = process_tids(self.pid())
+                .into_iter()
+                .filter(|tid| self.thread(*tid).is_none())
+                .collect();
+            if new_tids.is_empty() {
+                return;
+            }
+            for tid in new_tids {
+                // the thread may have exited meanwhile
+                if ptrace::attach(tid).is_ok() && waitpid(tid, Some(WaitPidFlag::__WALL)).is_ok() {
+                    self.add_thread(tid);
+                }
+            }
+        }
+    }
+
+    /// Asks the kernel to stop the inferior at clone, fork and exec, and to trace its new
+    /// threads and children.
+    fn set_trace_options(&self) -> Result<(), nix::Error> {
+        for tid in self.thread_ids() {
+            ptrace::setoptions(
+                tid,
+                ptrace::Options::PTRACE_O_TRACECLONE
+                    | ptrace::Options::PTRACE_O_TRACEFORK
+                    | ptrace::Options::PTRACE_O_TRACEVFORK
+                    | ptrace::Options::PTRACE_O_TRACEVFORKDONE
+                    | ptrace::Options::PTRACE_O_TRACEEXEC,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn thread_ids(&self) -> Vec<Pid> {
+        self.threads.iter().map(|thread| thread.tid).collect()
+    }
+
+    fn thread(&self, tid: Pid) -> Option<&Thread> {
+        self.threads.iter().find(|thread| thread.tid == tid)
+    }
+
+    fn thread_mut(&mut self, tid: Pid) -> Option<&mut Thread> {
+        self.threads.iter_mut().find(|thread| thread.tid == tid)
+    }
+
+    /// Starts tracking the stopped thread `tid`.
+    fn add_thread(&mut self, tid: Pid) {
+        self.threads.push(Thread {
+            num: self.next_thread_num,
+            tid,
+            running: false,
+            sigstop_pending: false,
+            pending_signal: None,
+        });
+        self.next_thread_num += 1;
+    }
+
+    /// Sets what happens when the inferior forks.
+    pub fn set_fork_options(&mut self, follow_fork_mode: FollowForkMode, detach_on_fork: bool) {
+        self.follow_fork_mode = follow_fork_mode;
+        self.detach_on_fork = detach_on_fork;
+    }
+
+    /// Sets which signals stop the inferior and which are passed on to it.
+    pub fn set_signal_table(&mut self, signal_table: &SignalTable) {
+        self.signal_table = signal_table.clone();
+    }
+
+    /// Hands over the forked processes we still trace.
+    pub fn take_forked(&mut self) -> Vec<Inferior> {
+        std::mem::take(&mut self.forked)
+    }
+
+    /// Whether an event of one of our threads is waiting in `stray_stops`, e.g. because the
+    /// process was killed while it was parked in the background. It is reported instead of
+    /// resuming.
+    fn has_pending_event(&self) -> bool {
+        self.stray_stops
+            .iter()
+            .any(|(tid, _)| self.thread(*tid).is_some())
+    }
+
+    /// Passes the events we collected for the threads of `other` on to it.
+    pub fn hand_over_strays(&mut self, other: &mut Inferior) {
+        let (theirs, ours): (Vec<_>, Vec<_>) = std::mem::take(&mut self.stray_stops)
+            .into_iter()
+            .partition(|(tid, _)| other.thread(*tid).is_some());
+        self.stray_stops = ours;
+        other.stray_stops.extend(theirs);
+    }
+
+    /// Makes the breakpoints in the process match `addrs`, inserting and removing 0xcc bytes as
+    /// needed.
+    pub fn set_breakpoints(&mut self, addrs: &[usize]) -> Result<(), nix::Error> {
+        let stale: Vec<usize> = self
+            .breakpoints
+            .keys()
+            .filter(|addr| !addrs.contains(addr))
+            .copied()
+            .collect();
+        for addr in stale {
+            self.remove_breakpoint(addr)?;
+        }
+        for addr in addrs {
+            self.append_breakpoint(*addr)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the program the process runs.
+    pub fn exe(&self) -> Option<String> {
+        fs::read_link(format!("/proc/{}/exe", self.pid()))
+            .ok()
+            .map(|exe| exe.to_string_lossy().to_string())
+    }
+
+    /// Removes every breakpoint and watchpoint and lets the process run on untraced.
+    pub fn detach(&mut self) -> Result<(), nix::Error> {
+        let addrs: Vec<usize> = self.breakpoints.keys().copied().collect();
+        for addr in addrs {
+            self.remove_breakpoint(addr)?;
+        }
+        for slot in 0..NUM_WATCHPOINT_SLOTS {
+            self.clear_watchpoint(slot)?;
+        }
+        for thread in self.threads.clone() {
+            if thread.sigstop_pending {
+                // let our SIGSTOP arrive now, or it would stop the process after we're gone
+                ptrace::cont(thread.tid, None)?;
+                while let WaitStatus::Stopped(_, sig) =
+                    waitpid(thread.tid, Some(WaitPidFlag::__WALL))?
+                {
+                    if sig == signal::SIGSTOP {
+                        break;
                     }
+                    ptrace::cont(thread.tid, None)?;
                 }
             }
+            ptrace::detach(thread.tid, thread.pending_signal)?;
+        }
+        Ok(())
+    }
+
+    /// Returns true if this inferior was attached to rather than spawned by us.
+    pub fn is_attached(&self) -> bool {
+        self.attached
+    }
+
+    /// Executes a single instruction. If a breakpoint sits on the current instruction, the
+    /// original byte is put back for the step and the 0xcc is re-inserted afterwards.
+    pub fn step_instruction(&mut self) -> Result<Status, nix::Error> {
+        if self.has_pending_event() {
+            return self.wait(None);
+        }
+        let rip = ptrace::getregs(self.tid())?.rip as usize;
+        if let Some(orig_byte) = self.breakpoints.get(&rip).copied() {
+            // 1. restore and step
+            self.write_byte(rip, orig_byte)?;
+            self.resume(true)?;
+            // 2. put the breakpoint back if the child is still alive (and the breakpoint wasn't
+            // taken out for a vfork meanwhile)
+            let status = self.wait(None)?;
+            if status.is_stopped() && self.breakpoints.contains_key(&rip) {
+                self.write_byte(rip, INT_CODE)?;
+            }
+            Ok(status)
+        } else {
+            self.resume(true)?;
+            self.wait(None)
+        }
+    }
+
+    /// Resumes the inferior. A single step only moves the current thread while the others stay
+    /// stopped; otherwise all threads run until one of them has something to report.
+    fn resume(&mut self, step: bool) -> Result<(), nix::Error> {
+        self.stepping = step;
+        if step {
+            return self.resume_thread(self.current, true);
+        }
+        for thread in self.threads.clone() {
+            if !thread.running {
+                self.resume_thread(thread.tid, false)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn resume_thread(&mut self, tid: Pid, step: bool) -> Result<(), nix::Error> {
+        let sig = self
+            .thread_mut(tid)
+            .and_then(|thread| thread.pending_signal.take());
+        let res = if step {
+            ptrace::step(tid, sig)
+        } else {
+            ptrace::cont(tid, sig)
+        };
+        match res {
+            // the thread is exiting; waitpid will tell us
+            Err(nix::Error::Sys(Errno::ESRCH)) if tid != self.current => {}
+            res => res?,
+        }
+        if let Some(thread) = self.thread_mut(tid) {
+            thread.running = true;
+        }
+        Ok(())
+    }
+
+    /// Stops the threads that are still running, so that the whole process holds still while
+    /// we look at it.
+    fn stop_all(&mut self) -> Result<(), nix::Error> {
+        let running: Vec<Pid> = self
+            .threads
+            .iter()
+            .filter(|thread| thread.running)
+            .map(|thread| thread.tid)
+            .collect();
+        for tid in running.iter() {
+            unsafe {
+                libc::syscall(
+                    libc::SYS_tgkill,
+                    self.pid().as_raw(),
+                    tid.as_raw(),
+                    libc::SIGSTOP,
+                );
+            }
+        }
+        for tid in running {
+            match waitpid(tid, Some(WaitPidFlag::__WALL))? {
+                WaitStatus::Stopped(_, signal::SIGSTOP) => {}
+                WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                    self.threads.retain(|thread| thread.tid != tid);
+                    println!("[LWP {} exited]", tid);
+                    continue;
+                }
+                WaitStatus::PtraceEvent(_, _, event) => {
+                    self.thread_mut(tid).unwrap().sigstop_pending = true;
+                    self.handle_event(tid, event, false)?;
+                }
+                // it stopped for something else first. If it hit a breakpoint, it will hit it
+                // again once resumed; other signals are delivered then.
+                status => {
+                    self.rewind_breakpoint(tid)?;
+                    if let WaitStatus::Stopped(_, signal) = status {
+                        self.queue_signal(tid, signal);
+                    }
+                    self.thread_mut(tid).unwrap().sigstop_pending = true;
+                }
+            }
+            if let Some(thread) = self.thread_mut(tid) {
+                thread.running = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Remembers to deliver `signal` to `tid` when it resumes, if the program is to see it.
+    fn queue_signal(&mut self, tid: Pid, signal: signal::Signal) {
+        // a stop signal that was delivered already shows up again as a group-stop, which has no
+        // siginfo. Passing it on once more would stop the process forever.
+        let delivered = ptrace::getsiginfo(tid).is_err();
+        if self.signal_table.get(signal).pass && !delivered {
+            if let Some(thread) = self.thread_mut(tid) {
+                thread.pending_signal = Some(signal);
+            }
+        }
+    }
+
+    /// Moves rip of `tid` back onto the breakpoint if it just executed one of our int3s.
+    /// Returns the (possibly corrected) rip.
+    fn rewind_breakpoint(&self, tid: Pid) -> Result<usize, nix::Error> {
+        let mut regs = ptrace::getregs(tid)?;
+        // an int3 leaves rip one byte past the breakpoint, so rewind it to make rip point at the
+        // breakpoint address again
+        let siginfo = ptrace::getsiginfo(tid)?;
+        if siginfo.si_signo == libc::SIGTRAP
+            && siginfo.si_code == SI_KERNEL
+            && self.breakpoints.contains_key(&(regs.rip as usize).wrapping_sub(1))
+        {
+            regs.rip -= 1;
+            ptrace::setregs(tid, regs)?;
+        }
+        Ok(regs.rip as usize)
+    }
+
+    pub fn cont(&mut self) -> Result<Status, nix::Error> {
+        let res = self.resume_until_stop();
+        // internal breakpoints only last for one resume, however it ended
+        let alive = res.as_ref().map_or(true, |status| status.is_stopped());
+        self.remove_internal_breakpoints(alive)?;
+        res
+    }
+
+    fn resume_until_stop(&mut self) -> Result<Status, nix::Error> {
+        if self.has_pending_event() {
+            return self.wait(None);
+        }
+        let rip = ptrace::getregs(self.tid())?.rip as usize;
+        if self.breakpoints.contains_key(&rip) {
+            match self.step_instruction()? {
+                Status::Stopped(signal::SIGTRAP, _) => {}
+                other => return Ok(other),
+            }
         }
         // continue
-        ptrace::cont(self.pid(), None)?;
+        self.resume(false)?;
         self.wait(None)
     }
 
-    pub fn kill(&mut self) {
-        self.child.kill().unwrap();
-        let _wait_res = self.wait(None).unwrap(); // SIGKILL
-        println!("Killing running inferior (pid {})", self.pid());
+    /// Sets breakpoints at `addrs` that `cont` removes again once the inferior stops or exits.
+    pub fn set_internal_breakpoints(&mut self, addrs: &[usize]) -> Result<(), nix::Error> {
+        for &addr in addrs {
+            if !self.breakpoints.contains_key(&addr) {
+                self.append_breakpoint(addr)?;
+                self.internal_breakpoints.push(addr);
+            }
+        }
+        Ok(())
     }
 
-    /// Returns the pid of this inferior.
-    pub fn pid(&self) -> Pid {
-        nix::unistd::Pid::from_raw(self.child.id() as i32)
+    /// Takes the internal breakpoints out. The original bytes are only written back if the
+    /// process is still around with the same program (an exec wipes all breakpoints).
+    fn remove_internal_breakpoints(&mut self, alive: bool) -> Result<(), nix::Error> {
+        for addr in std::mem::take(&mut self.internal_breakpoints) {
+            if let Some(orig_byte) = self.breakpoints.remove(&addr) {
+                if alive {
+                    self.write_byte(addr, orig_byte)?;
+                }
+            }
+        }
+        Ok(())
     }
 
-    pub fn backtrace(&self, debug_data:&DwarfData) {
-        let regs = ptrace::getregs(self.pid()).unwrap();
-        let mut rip = regs.rip as usize;
-        let mut rbp = regs.rbp as usize;
-        loop{
-            // 1. print function/line number for instruction_ptr
-            let rip_line = match debug_data.get_line_from_addr(rip){
-                Some(v) => v,
-                None => {
-                    println!("there is no call stack yet");
-                    continue;
+    /// Reads the return address on top of the stack, i.e. right after a call instruction.
+    pub fn read_return_address(&self) -> Result<usize, nix::Error> {
+        let rsp = ptrace::getregs(self.tid())?.rsp;
+        Ok(ptrace::read(self.tid(), rsp as ptrace::AddressType)? as usize)
+    }
+
+    /// Returns true if the instruction at `addr` is a call.
+    pub fn is_call_instruction(&self, addr: usize) -> Result<bool, nix::Error> {
+        let code = self.read_bytes(addr, 8)?;
+        // skip legacy and REX prefixes
+        let opcode_idx = match code
+            .iter()
+            .position(|b| !matches!(b, 0x40..=0x4f | 0x66 | 0x67 | 0xf2 | 0xf3))
+        {
+            Some(idx) if idx + 1 < code.len() => idx,
+            _ => return Ok(false),
+        };
+        Ok(match code[opcode_idx] {
+            // call rel32
+            0xe8 => true,
+            // call r/m64 (ff /2)
+            0xff => (code[opcode_idx + 1] >> 3) & 0x7 == 2,
+            _ => false,
+        })
+    }
+
+    /// Reads memory with a single process_vm_readv call.
+    fn read_memory_bulk(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        if len == 0 {
+            return Ok(Vec::new());
+        }
+        let mut bytes = vec![0u8; len];
+        let local = libc::iovec {
+            iov_base: bytes.as_mut_ptr() as *mut std::ffi::c_void,
+            iov_len: len,
+        };
+        let remote = libc::iovec {
+            iov_base: addr as *mut std::ffi::c_void,
+            iov_len: len,
+        };
+        let res = unsafe { libc::process_vm_readv(self.pid().as_raw(), &local, 1, &remote, 1, 0) };
+        if res < 0 {
+            return Err(nix::Error::Sys(Errno::last()));
+        }
+        bytes.truncate(res as usize);
+        Ok(bytes)
+    }
+
+    /// Reads memory one word at a time with PTRACE_PEEKDATA.
+    fn read_memory_words(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = Vec::with_capacity(len);
+        let mut word_addr = _align_addr_to_word(addr);
+        while word_addr < addr + len {
+            match ptrace::read(self.tid(), word_addr as ptrace::AddressType) {
+                Ok(word) => bytes.extend_from_slice(&(word as u64).to_le_bytes()),
+                Err(err) if bytes.is_empty() => return Err(err),
+                Err(_) => break,
+            }
+            word_addr += size_of::<usize>();
+        }
+        let skip = addr - _align_addr_to_word(addr);
+        let end = (skip + len).min(bytes.len());
+        if skip >= end {
+            return Err(nix::Error::Sys(Errno::EIO));
+        }
+        Ok(bytes.drain(skip..end).collect())
+    }
+
+    /// Sets the register called `name`. Returns false if there is no such register.
+    pub fn set_register(&mut self, name: &str, value: u64) -> Result<bool, nix::Error> {
+        let mut regs = ptrace::getregs(self.tid())?;
+        match registers::register_mut(&mut regs, name) {
+            Some(reg) => *reg = value,
+            None => return Ok(false),
+        }
+        ptrace::setregs(self.tid(), regs)?;
+        Ok(true)
+    }
+
+    pub fn kill(&mut self) {
+        // threads that were just created may not have reported to us yet
+        let mut tids = process_tids(self.pid());
+        for tid in self.thread_ids() {
+            if !tids.contains(&tid) {
+                tids.push(tid);
+            }
+        }
+        // it may be dead already
+        signal::kill(self.pid(), signal::SIGKILL).ok();
+        // reap every thread, but only ours: the other inferiors are our children too. The leader
+        // is reported last.
+        tids.retain(|tid| *tid != self.pid());
+        tids.push(self.pid());
+        for tid in tids {
+            loop {
+                match waitpid(tid, Some(WaitPidFlag::__WALL)) {
+                    Ok(WaitStatus::Exited(_, _)) | Ok(WaitStatus::Signaled(_, _, _)) | Err(_) => {
+                        break
+                    }
+                    Ok(_) => {}
                 }
-            };
-            let rip_func = match debug_data.get_function_from_addr(rip){
-                Some(v) => v,
+            }
+        }
+        println!("Killing running inferior (pid {})", self.pid());
+    }
+
+    /// Waits until a thread of this inferior has something to report and returns a Status to
+    /// indicate the state of the process. Events we handle on our own (e.g. new threads, or forks
+    /// in follow-fork-mode parent) resume the thread and keep waiting. Once a thread stops, the
+    /// other threads are stopped too.
+    pub fn wait(&mut self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
+        let options = options.unwrap_or(WaitPidFlag::empty()) | WaitPidFlag::__WALL;
+        loop {
+            let own_stray = self
+                .stray_stops
+                .iter()
+                .position(|(tid, _)| self.thread(*tid).is_some());
+            let (tid, status) = match own_stray {
+                Some(pos) => self.stray_stops.remove(pos),
                 None => {
-                    println!("there is no call stack yet");
-                    continue;
+                    // threads are children of the process, not of us, so wait for anyone
+                    let status = waitpid(Pid::from_raw(-1), Some(options))?;
+                    match status.pid() {
+                        Some(tid) => (tid, status),
+                        None => continue,
+                    }
                 }
             };
-            println!("{} ({:?}:{})", rip_func, rip_line.file, rip_line.number);
-            // 2. compare and quit
-            if rip_func == "main"{
-                break
+            if self.thread(tid).is_none() {
+                self.stray_stops.push((tid, status));
+                continue;
+            }
+            if let Some(thread) = self.thread_mut(tid) {
+                thread.running = false;
+            }
+            if let Some(status) = self.handle_wait_status(tid, status)? {
+                if status.is_stopped() {
+                    self.stop_all()?;
+                }
+                return Ok(status);
             }
-            rip = ptrace::read(self.pid(), (rbp + 8) as ptrace::AddressType).unwrap() as usize;
-            rbp = ptrace::read(self.pid(), rbp as ptrace::AddressType).unwrap() as usize;
         }
     }
 
-    /// Calls waitpid on this inferior and returns a Status to indicate the state of the process
-    /// after the waitpid call.
-    pub fn wait(&self, options: Option<WaitPidFlag>) -> Result<Status, nix::Error> {
-        Ok(match waitpid(self.pid(), options)? {
-            WaitStatus::Exited(_pid, exit_code) => Status::Exited(exit_code),
-            WaitStatus::Signaled(_pid, signal, _core_dumped) => Status::Signaled(signal),
-            WaitStatus::Stopped(_pid, signal) => {
-                let regs = ptrace::getregs(self.pid())?;
-                Status::Stopped(signal, regs.rip as usize)
+    /// Turns what waitpid said about thread `tid` into a Status, or returns None if the
+    /// inferior was resumed.
+    fn handle_wait_status(
+        &mut self,
+        tid: Pid,
+        status: WaitStatus,
+    ) -> Result<Option<Status>, nix::Error> {
+        Ok(Some(match status {
+            WaitStatus::Exited(_, exit_code) if tid == self.pid() => Status::Exited(exit_code),
+            WaitStatus::Signaled(_, signal, _core_dumped) if tid == self.pid() => {
+                Status::Signaled(signal)
             }
-            other => panic!("waitpid returned unexpected status: {:?}", other),
-        })
+            WaitStatus::Exited(_, _) | WaitStatus::Signaled(_, _, _) => {
+                self.threads.retain(|thread| thread.tid != tid);
+                println!("[LWP {} exited]", tid);
+                if tid == self.current {
+                    self.current = self.pid();
+                    if self.stepping {
+                        // the thread we were stepping is gone, so let the others go on
+                        self.resume(false)?;
+                    }
+                }
+                return Ok(None);
+            }
+            WaitStatus::Stopped(_, signal::SIGSTOP) if self.thread(tid).unwrap().sigstop_pending => {
+                // a SIGSTOP we sent long ago to stop the thread
+                self.thread_mut(tid).unwrap().sigstop_pending = false;
+                let step = self.stepping && tid == self.current;
+                self.resume_thread(tid, step)?;
+                return Ok(None);
+            }
+            WaitStatus::Stopped(_, signal) if signal != signal::SIGTRAP => {
+                self.queue_signal(tid, signal);
+                let policy = self.signal_table.get(signal);
+                if !policy.stop {
+                    if policy.print {
+                        println!(
+                            "Program received signal {:?}, {}.",
+                            signal,
+                            signals::description(signal)
+                        );
+                    }
+                    let step = self.stepping && tid == self.current;
+                    self.resume_thread(tid, step)?;
+                    return Ok(None);
+                }
+                if tid != self.current && self.threads.len() > 1 {
+                    println!("[Switching to LWP {}]", tid);
+                }
+                self.current = tid;
+                Status::Stopped(signal, ptrace::getregs(tid)?.rip as usize)
+            }
+            WaitStatus::Stopped(_, signal) => {
+                if tid != self.current && self.threads.len() > 1 {
+                    println!("[Switching to LWP {}]", tid);
+                }
+                self.current = tid;
+                let rip = match signal {
+                    signal::SIGTRAP => self.rewind_breakpoint(tid)?,
+                    _ => ptrace::getregs(tid)?.rip as usize,
+                };
+                if signal == signal::SIGTRAP {
+                    // DR6 tells which watchpoint (if any) fired; the CPU never clears it
+                    let dr6 = self.read_debugreg(tid, DR6)?;
+                    if let Some(slot) = (0..NUM_WATCHPOINT_SLOTS).find(|slot| dr6 & (1 << slot) != 0) {
+                        self.write_debugreg(tid, DR6, 0)?;
+                        return Ok(Some(Status::Watchpoint(slot, rip)));
+                    }
+                }
+                Status::Stopped(signal, rip)
+            }
+            WaitStatus::PtraceEvent(_, _, event) => return self.handle_event(tid, event, true),
+            WaitStatus::PtraceSyscall(_) => {
+                let step = self.stepping;
+                self.resume_thread(tid, step)?;
+                return Ok(None);
+            }
+            WaitStatus::Continued(_) | WaitStatus::StillAlive => return Ok(None),
+        }))
+    }
+
+    /// Handles a PTRACE_EVENT_* stop of thread `tid`. Returns None if the thread was resumed,
+    /// or was left stopped because `resume` is false.
+    fn handle_event(
+        &mut self,
+        tid: Pid,
+        event: i32,
+        resume: bool,
+    ) -> Result<Option<Status>, nix::Error> {
+        let step = self.stepping && tid == self.current;
+        match event {
+            libc::PTRACE_EVENT_CLONE => {
+                let new_tid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                self.wait_for_new(new_tid)?;
+                self.copy_watchpoints(tid, new_tid)?;
+                self.add_thread(new_tid);
+                println!("[New LWP {}]", new_tid);
+                // while stepping, only the current thread may run
+                if resume && !self.stepping {
+                    self.resume_thread(new_tid, false)?;
+                }
+            }
+            libc::PTRACE_EVENT_FORK | libc::PTRACE_EVENT_VFORK => {
+                let child_pid = Pid::from_raw(ptrace::getevent(tid)? as i32);
+                let vfork = event == libc::PTRACE_EVENT_VFORK;
+                let mut child = self.forked_child(child_pid, vfork)?;
+                if self.follow_fork_mode == FollowForkMode::Child {
+                    self.forked.push(child);
+                    if resume {
+                        return Ok(Some(Status::Forked(child_pid)));
+                    }
+                } else if self.detach_on_fork {
+                    if vfork {
+                        // the child runs in our memory until it execs or exits, so it must not
+                        // trip over our breakpoints
+                        self.vfork_breakpoints = self.breakpoints.keys().copied().collect();
+                        for addr in self.vfork_breakpoints.clone() {
+                            self.remove_breakpoint(addr)?;
+                        }
+                    }
+                    child.detach()?;
+                    println!("[Detaching after fork from child process {}]", child_pid);
+                } else {
+                    self.forked.push(child);
+                }
+            }
+            libc::PTRACE_EVENT_VFORK_DONE => {
+                for addr in std::mem::take(&mut self.vfork_breakpoints) {
+                    self.append_breakpoint(addr)?;
+                }
+            }
+            libc::PTRACE_EVENT_EXEC => {
+                self.breakpoints.clear();
+                self.vfork_breakpoints.clear();
+                // exec kills all other threads, and the one that called it takes over the pid
+                let pid = self.pid();
+                self.threads.retain(|thread| thread.tid == pid);
+                self.current = pid;
+                let rip = ptrace::getregs(pid)?.rip as usize;
+                return Ok(Some(Status::Execed(rip)));
+            }
+            _ => {}
+        }
+        if resume {
+            self.resume_thread(tid, step)?;
+        }
+        Ok(None)
+    }
+
+    /// Waits for the first stop of a new thread or child process, which may have been reported
+    /// already.
+    fn wait_for_new(&mut self, pid: Pid) -> Result<(), nix::Error> {
+        match self.stray_stops.iter().position(|(stray, _)| *stray == pid) {
+            Some(pos) => {
+                self.stray_stops.remove(pos);
+            }
+            None => {
+                waitpid(pid, Some(WaitPidFlag::__WALL))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Takes over the child `pid` the inferior just forked. The kernel already made us trace it
+    /// and stops it right away. Unless it shares our memory (vfork), it has a copy of our
+    /// breakpoints.
+    fn forked_child(&mut self, pid: Pid, shares_memory: bool) -> Result<Inferior, nix::Error> {
+        self.wait_for_new(pid)?;
+        let mut child = Inferior::from_pid(pid, self.attached);
+        child.set_fork_options(self.follow_fork_mode, self.detach_on_fork);
+        child.set_signal_table(&self.signal_table);
+        if !shares_memory {
+            child.breakpoints = self.breakpoints.clone();
+            // our internal breakpoints are of no use to the child
+            for &addr in self.internal_breakpoints.iter() {
+                child.remove_breakpoint(addr)?;
+            }
+        }
+        Ok(child)
+    }
+}
+
+impl ProgramState for Inferior {
+    fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    fn tid(&self) -> Pid {
+        self.current
+    }
+
+    fn thread_list(&self) -> Vec<(usize, Pid)> {
+        self.threads
+            .iter()
+            .map(|thread| (thread.num, thread.tid))
+            .collect()
+    }
+
+    fn select_thread(&mut self, num: usize) -> bool {
+        match self.threads.iter().find(|thread| thread.num == num) {
+            Some(thread) => {
+                self.current = thread.tid;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn current_thread_num(&self) -> usize {
+        self.thread(self.current).map_or(0, |thread| thread.num)
+    }
+
+    /// Our breakpoints are replaced with the original bytes.
+    fn read_memory(&self, addr: usize, len: usize) -> Result<Vec<u8>, nix::Error> {
+        let mut bytes = match self.read_memory_bulk(addr, len) {
+            Ok(bytes) => bytes,
+            // e.g. kernels without process_vm_readv
+            Err(nix::Error::Sys(Errno::ENOSYS)) | Err(nix::Error::Sys(Errno::EPERM)) => {
+                self.read_memory_words(addr, len)?
+            }
+            Err(err) => return Err(err),
+        };
+        for (brk, orig_byte) in self.breakpoints.iter() {
+            if *brk >= addr && *brk < addr + bytes.len() {
+                bytes[*brk - addr] = *orig_byte;
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn get_regs(&self) -> Result<libc::user_regs_struct, nix::Error> {
+        ptrace::getregs(self.tid())
+    }
+
+    fn read_xmm(&self, xmm: usize) -> Option<u64> {
+        let mut fpregs: libc::user_fpregs_struct = unsafe { std::mem::zeroed() };
+        let res = unsafe {
+            libc::ptrace(
+                libc::PTRACE_GETFPREGS,
+                self.tid().as_raw(),
+                0,
+                &mut fpregs as *mut libc::user_fpregs_struct,
+            )
+        };
+        if res < 0 {
+            return None;
+        }
+        let low = fpregs.xmm_space[xmm * 4] as u64;
+        let high = fpregs.xmm_space[xmm * 4 + 1] as u64;
+        Some(low | (high << 32))
+    }
+
+    fn fault_address(&self) -> Option<usize> {
+        let siginfo = ptrace::getsiginfo(self.tid()).ok()?;
+        if siginfo.si_signo != libc::SIGSEGV && siginfo.si_signo != libc::SIGBUS {
+            return None;
+        }
+        Some(unsafe { siginfo.si_addr() } as usize)
+    }
+
+    fn auxv(&self) -> Option<Vec<u8>> {
+        fs::read(format!("/proc/{}/auxv", self.pid())).ok()
     }
 }
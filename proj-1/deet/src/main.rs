@@ -1,8 +1,17 @@
+mod breakpoint;
+mod core_file;
 mod debugger;
 mod debugger_command;
+mod disassemble;
+mod examine;
+mod expression;
 mod inferior;
+mod registers;
+mod shared_library;
+mod signals;
 mod dwarf_data;
 mod gimli_wrapper;
+mod source;
 
 use crate::debugger::Debugger;
 use nix::sys::signal::{signal, SigHandler, Signal};
@@ -10,15 +19,48 @@ use std::env;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: {} <target program>", args[0]);
-        std::process::exit(1);
-    }
-    let target = &args[1];
+    let mut core = None;
+    let (target, attach_pid) = match args.len() {
+        2 => (args[1].clone(), None),
+        3 if args[1] == "-p" => {
+            let pid = match args[2].parse::<i32>() {
+                Ok(pid) => pid,
+                Err(_) => {
+                    println!("Invalid pid {}", args[2]);
+                    std::process::exit(1);
+                }
+            };
+            // debug whatever binary the process is running
+            match std::fs::read_link(format!("/proc/{}/exe", pid)) {
+                Ok(exe) => (exe.to_string_lossy().to_string(), Some(pid)),
+                Err(_) => {
+                    println!("No such process {}", pid);
+                    std::process::exit(1);
+                }
+            }
+        }
+        3 => {
+            core = Some(args[2].clone());
+            (args[1].clone(), None)
+        }
+        _ => {
+            println!("Usage: {} <target program>", args[0]);
+            println!("       {} <target program> <core file>", args[0]);
+            println!("       {} -p <pid>", args[0]);
+            std::process::exit(1);
+        }
+    };
 
     // Disable handling of ctrl+c in this process (so that ctrl+c only gets delivered to child
     // processes)
     unsafe { signal(Signal::SIGINT, SigHandler::SigIgn) }.expect("Error disabling SIGINT handling");
 
-    Debugger::new(target).run();
+    let mut debugger = Debugger::new(&target);
+    if let Some(pid) = attach_pid {
+        debugger.attach(pid);
+    }
+    if let Some(core) = core {
+        debugger.load_core(&core);
+    }
+    debugger.run();
 }
@@ -0,0 +1,90 @@
+use libc::user_regs_struct;
+
+/// Registers shown by "info registers", in gdb's order.
+pub const REGISTER_NAMES: [&str; 26] = [
+    "rax", "rbx", "rcx", "rdx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15", "rip", "eflags", "cs", "ss", "ds", "es", "fs", "gs", "fs_base", "gs_base",
+];
+
+/// Names of the eflags bits that gdb shows, by bit number
+const EFLAGS_BITS: [(u32, &str); 9] = [
+    (0, "CF"),
+    (2, "PF"),
+    (4, "AF"),
+    (6, "ZF"),
+    (7, "SF"),
+    (8, "TF"),
+    (9, "IF"),
+    (10, "DF"),
+    (11, "OF"),
+];
+
+/// Returns the register called `name` in `regs`. Accepts gdb's $pc, $sp and $fp aliases.
+pub fn register_mut<'a>(regs: &'a mut user_regs_struct, name: &str) -> Option<&'a mut u64> {
+    let reg = match name {
+        "rax" => &mut regs.rax,
+        "rbx" => &mut regs.rbx,
+        "rcx" => &mut regs.rcx,
+        "rdx" => &mut regs.rdx,
+        "rsi" => &mut regs.rsi,
+        "rdi" => &mut regs.rdi,
+        "rbp" | "fp" => &mut regs.rbp,
+        "rsp" | "sp" => &mut regs.rsp,
+        "r8" => &mut regs.r8,
+        "r9" => &mut regs.r9,
+        "r10" => &mut regs.r10,
+        "r11" => &mut regs.r11,
+        "r12" => &mut regs.r12,
+        "r13" => &mut regs.r13,
+        "r14" => &mut regs.r14,
+        "r15" => &mut regs.r15,
+        "rip" | "pc" => &mut regs.rip,
+        "eflags" => &mut regs.eflags,
+        "cs" => &mut regs.cs,
+        "ss" => &mut regs.ss,
+        "ds" => &mut regs.ds,
+        "es" => &mut regs.es,
+        "fs" => &mut regs.fs,
+        "gs" => &mut regs.gs,
+        "fs_base" => &mut regs.fs_base,
+        "gs_base" => &mut regs.gs_base,
+        _ => return None,
+    };
+    Some(reg)
+}
+
+/// Returns true for registers that hold addresses rather than plain numbers.
+pub fn is_pointer_register(name: &str) -> bool {
+    match name {
+        "rip" | "pc" | "rsp" | "sp" | "rbp" | "fp" | "fs_base" | "gs_base" => true,
+        _ => false,
+    }
+}
+
+/// Decodes eflags the way gdb shows it, e.g. "[ ZF PF IF ]".
+pub fn format_eflags(eflags: u64) -> String {
+    let flags: Vec<&str> = EFLAGS_BITS
+        .iter()
+        .filter(|(bit, _)| eflags & (1 << bit) != 0)
+        .map(|(_, name)| *name)
+        .collect();
+    format!("[ {} ]", flags.join(" "))
+}
+
+/// Returns the name of DWARF register number `register` in the x86-64 psABI numbering, for the
+/// general purpose registers and rip.
+pub fn dwarf_register_name(register: u16) -> Option<&'static str> {
+    const NAMES: [&str; 17] = [
+        "rax", "rdx", "rcx", "rbx", "rsi", "rdi", "rbp", "rsp", "r8", "r9", "r10", "r11", "r12",
+        "r13", "r14", "r15", "rip",
+    ];
+    NAMES.get(register as usize).copied()
+}
+
+/// DWARF register numbers 17 to 32 are xmm0 to xmm15.
+pub fn dwarf_xmm_register(register: u16) -> Option<usize> {
+    match register {
+        17..=32 => Some(register as usize - 17),
+        _ => None,
+    }
+}
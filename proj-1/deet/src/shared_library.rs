@@ -0,0 +1,108 @@
+use crate::inferior::ProgramState;
+use std::convert::TryInto;
+
+/// Dynamic section tags
+const DT_NULL: usize = 0;
+const DT_DEBUG: usize = 21;
+
+/// Offsets into struct r_debug
+const R_DEBUG_MAP: usize = 8;
+const R_DEBUG_BRK: usize = 16;
+const R_DEBUG_STATE: usize = 24;
+/// r_state while no object is being added or removed
+const RT_CONSISTENT: u32 = 0;
+/// Offsets into struct link_map
+const LINK_MAP_ADDR: usize = 0;
+const LINK_MAP_NAME: usize = 8;
+const LINK_MAP_NEXT: usize = 24;
+
+/// Stop walking lists in case the program's memory is corrupted into a cycle.
+const MAX_DYNAMIC_ENTRIES: usize = 1024;
+const MAX_LIBRARIES: usize = 4096;
+const MAX_PATH_LEN: usize = 4096;
+/// Memory is mapped in pages of this size on x86-64
+const PAGE_SIZE: usize = 4096;
+
+/// A shared object in the dynamic linker's list of loaded objects.
+#[derive(Debug)]
+pub struct LoadedObject {
+    pub path: String,
+    /// Difference between the addresses the object was loaded at and its link-time addresses
+    pub load_bias: usize,
+}
+
+fn read_word(program: &dyn ProgramState, addr: usize) -> Option<usize> {
+    let bytes = program.read_bytes(addr, 8).ok()?;
+    Some(u64::from_le_bytes(bytes[..].try_into().ok()?) as usize)
+}
+
+fn read_string(program: &dyn ProgramState, addr: usize) -> Option<String> {
+    let mut bytes = Vec::new();
+    while bytes.len() < MAX_PATH_LEN {
+        // don't read into the next page, which may not be mapped
+        let chunk_addr = addr.checked_add(bytes.len())?;
+        let chunk_len = 64.min(PAGE_SIZE - chunk_addr % PAGE_SIZE);
+        let chunk = program.read_bytes(chunk_addr, chunk_len).ok()?;
+        match chunk.iter().position(|b| *b == 0) {
+            Some(end) => {
+                bytes.extend_from_slice(&chunk[..end]);
+                return Some(String::from_utf8_lossy(&bytes).to_string());
+            }
+            None => bytes.extend(chunk),
+        }
+    }
+    None
+}
+
+/// Returns the address of the dynamic linker's struct r_debug, which it publishes in the
+/// DT_DEBUG entry of the executable's dynamic section at `dynamic`. None until the dynamic linker
+/// has initialized it.
+pub fn find_r_debug(program: &dyn ProgramState, dynamic: usize) -> Option<usize> {
+    for i in 0..MAX_DYNAMIC_ENTRIES {
+        let tag = read_word(program, dynamic + i * 16)?;
+        match tag {
+            DT_NULL => return None,
+            DT_DEBUG => {
+                let r_debug = read_word(program, dynamic + i * 16 + 8)?;
+                return if r_debug == 0 { None } else { Some(r_debug) };
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Returns r_brk, the function the dynamic linker calls before and after it changes the list of
+/// loaded objects.
+pub fn event_address(program: &dyn ProgramState, r_debug: usize) -> Option<usize> {
+    read_word(program, r_debug + R_DEBUG_BRK)
+}
+
+/// Whether the list of loaded objects can be read, i.e. the dynamic linker isn't in the middle of
+/// changing it.
+pub fn is_consistent(program: &dyn ProgramState, r_debug: usize) -> bool {
+    // r_state is an int
+    read_word(program, r_debug + R_DEBUG_STATE).map_or(false, |state| state as u32 == RT_CONSISTENT)
+}
+
+/// Walks the link_map list of `r_debug`. The executable itself and the vDSO have no path and are
+/// left out.
+pub fn loaded_objects(program: &dyn ProgramState, r_debug: usize) -> Vec<LoadedObject> {
+    let mut objects = Vec::new();
+    let mut link_map = read_word(program, r_debug + R_DEBUG_MAP).unwrap_or(0);
+    for _ in 0..MAX_LIBRARIES {
+        if link_map == 0 {
+            break;
+        }
+        let load_bias = read_word(program, link_map + LINK_MAP_ADDR);
+        let path = read_word(program, link_map + LINK_MAP_NAME)
+            .and_then(|name| read_string(program, name));
+        if let (Some(load_bias), Some(path)) = (load_bias, path) {
+            if path.starts_with('/') {
+                objects.push(LoadedObject { path, load_bias });
+            }
+        }
+        link_map = read_word(program, link_map + LINK_MAP_NEXT).unwrap_or(0);
+    }
+    objects
+}
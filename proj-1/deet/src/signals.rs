@@ -0,0 +1,108 @@
+use nix::sys::signal::Signal;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ffi::CStr;
+
+/// What to do when the inferior receives a signal, as set with "handle".
+#[derive(Debug, Clone, Copy)]
+pub struct SignalPolicy {
+    /// Give control back to the user
+    pub stop: bool,
+    /// Say that the signal arrived
+    pub print: bool,
+    /// Deliver the signal to the program when it resumes
+    pub pass: bool,
+}
+
+/// Signals that programs use routinely, so stopping for them is more annoying than useful
+/// (gdb's defaults)
+const QUIET_SIGNALS: [Signal; 7] = [
+    Signal::SIGALRM,
+    Signal::SIGURG,
+    Signal::SIGCHLD,
+    Signal::SIGWINCH,
+    Signal::SIGIO,
+    Signal::SIGVTALRM,
+    Signal::SIGPROF,
+];
+
+/// Signals the debugger itself uses, which the program never sees by default
+const DEBUGGER_SIGNALS: [Signal; 2] = [Signal::SIGTRAP, Signal::SIGINT];
+
+/// The policy for every signal.
+#[derive(Debug, Clone)]
+pub struct SignalTable {
+    policies: HashMap<Signal, SignalPolicy>,
+}
+
+impl SignalTable {
+    pub fn new() -> SignalTable {
+        let policies = Signal::iterator()
+            .map(|signal| {
+                let quiet = QUIET_SIGNALS.contains(&signal);
+                let policy = SignalPolicy {
+                    stop: !quiet,
+                    print: !quiet,
+                    pass: !DEBUGGER_SIGNALS.contains(&signal),
+                };
+                (signal, policy)
+            })
+            .collect();
+        SignalTable { policies }
+    }
+
+    pub fn get(&self, signal: Signal) -> SignalPolicy {
+        self.policies[&signal]
+    }
+
+    pub fn get_mut(&mut self, signal: Signal) -> &mut SignalPolicy {
+        self.policies.get_mut(&signal).unwrap()
+    }
+
+    /// Formats the "info signals" line of `signal`.
+    pub fn format_row(&self, signal: Signal) -> String {
+        let policy = self.get(signal);
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+        format!(
+            "{:<14}{}\t{}\t{}\t\t{}",
+            format!("{:?}", signal),
+            yes_no(policy.stop),
+            yes_no(policy.print),
+            yes_no(policy.pass),
+            description(signal)
+        )
+    }
+}
+
+/// Header for the lines of `SignalTable::format_row`.
+pub const TABLE_HEADER: &str = "Signal        Stop\tPrint\tPass to program\tDescription";
+
+/// Parses a signal name such as "SIGUSR1" or "usr1", or a number from 1 to 15 like gdb.
+pub fn parse_signal(name: &str) -> Result<Signal, String> {
+    if let Ok(num) = name.parse::<i32>() {
+        return match num {
+            1..=15 => Ok(Signal::try_from(num).unwrap()),
+            _ => Err("Only signals 1-15 are valid as numeric signals.".to_string()),
+        };
+    }
+    let upper = name.to_uppercase();
+    let upper = if upper.starts_with("SIG") {
+        upper
+    } else {
+        format!("SIG{}", upper)
+    };
+    Signal::iterator()
+        .find(|signal| format!("{:?}", signal) == upper)
+        .ok_or(format!("Unrecognized signal name \"{}\".", name))
+}
+
+/// Returns the human readable name of `signal`, e.g. "Segmentation fault".
+pub fn description(signal: Signal) -> String {
+    let desc = unsafe { libc::strsignal(signal as libc::c_int) };
+    if desc.is_null() {
+        return String::new();
+    }
+    unsafe { CStr::from_ptr(desc) }
+        .to_string_lossy()
+        .to_string()
+}
@@ -0,0 +1,58 @@
+use std::fs;
+
+/// Number of lines printed by one "list"
+const LIST_SIZE: usize = 10;
+
+/// Where the last "list" stopped, so that a bare "list" can continue from there.
+#[derive(Debug, Clone)]
+pub struct ListPosition {
+    pub file: String,
+    pub next_line: usize,
+}
+
+/// Reads the lines of a source file, or None if it can't be read.
+pub fn read_lines(path: &str) -> Option<Vec<String>> {
+    Some(
+        fs::read_to_string(path)
+            .ok()?
+            .lines()
+            .map(|line| line.to_string())
+            .collect(),
+    )
+}
+
+/// Returns the text of line `number` (1-based) of `path`.
+pub fn get_line(path: &str, number: usize) -> Option<String> {
+    read_lines(path)?.get(number.checked_sub(1)?).cloned()
+}
+
+/// Prints LIST_SIZE lines of `path` starting at `first`, marking `current` with an arrow.
+/// Returns where the next listing should start.
+pub fn print_lines(path: &str, first: usize, current: Option<usize>) -> Option<ListPosition> {
+    let lines = match read_lines(path) {
+        Some(lines) => lines,
+        None => {
+            println!("Err: cannot read source file {}", path);
+            return None;
+        }
+    };
+    let first = first.max(1);
+    if first > lines.len() {
+        println!("Line number {} out of range; \"{}\" has {} lines.", first, path, lines.len());
+        return None;
+    }
+    let last = (first + LIST_SIZE - 1).min(lines.len());
+    for number in first..=last {
+        let marker = if Some(number) == current { "=>" } else { "  " };
+        println!("{} {:<4}\t{}", marker, number, lines[number - 1]);
+    }
+    Some(ListPosition {
+        file: path.to_string(),
+        next_line: last + 1,
+    })
+}
+
+/// Prints the lines around `line` of `path`.
+pub fn print_around(path: &str, line: usize, current: Option<usize>) -> Option<ListPosition> {
+    print_lines(path, line.saturating_sub(LIST_SIZE / 2), current)
+}